@@ -0,0 +1,35 @@
+//! Compares rendering on a single thread with rendering concurrently at several resolutions, so that performance
+//! regressions are noticed.  Run with `cargo bench`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use mandelbrot::{Complex, RenderParams, concurrent_render, render};
+
+const RESOLUTIONS: [(usize, usize); 3] = [(200, 150), (400, 300), (800, 600)];
+
+fn params(bounds: (usize, usize)) -> RenderParams {
+    RenderParams::around(Complex { re: -0.75, im: 0.0 }, 1.0, bounds)
+}
+
+fn render_resolutions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+
+    for &bounds in RESOLUTIONS.iter() {
+        let params = params(bounds);
+        let mut pixels = vec![0; bounds.0 * bounds.1];
+        let resolution = format!("{}x{}", bounds.0, bounds.1);
+        group.throughput(Throughput::Elements((bounds.0 * bounds.1) as u64));
+
+        group.bench_with_input(BenchmarkId::new("single", &resolution), &params, |b, params| {
+            b.iter(|| render(&mut pixels, params))
+        });
+        group.bench_with_input(BenchmarkId::new("concurrent", &resolution), &params, |b, params| {
+            b.iter(|| concurrent_render(&mut pixels, params))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, render_resolutions);
+criterion_main!(benches);
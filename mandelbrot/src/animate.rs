@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+
+use mandelbrot::output::write_image;
+use mandelbrot::{Complex, RenderParams, concurrent_render};
+
+/// Hundredths of a second each frame is shown for in the animated GIF (25 frames per second)
+const GIF_FRAME_DELAY: u16 = 4;
+
+#[derive(Debug)]
+pub(crate) struct Animation {
+    pub(crate) bounds: (usize, usize),
+    pub(crate) center: Complex<f64>,
+    pub(crate) start_zoom: f64,
+    pub(crate) end_zoom: f64,
+    pub(crate) frames: usize,
+    pub(crate) samples: usize,
+}
+
+impl Animation {
+    /// The zoom of the given frame.  The zoom is interpolated geometrically, so that every frame magnifies the
+    /// previous one by the same factor, and eased so that the animation starts and ends smoothly.
+    fn zoom(&self, frame: usize) -> f64 {
+        let t = if self.frames > 1 {
+            frame as f64 / (self.frames - 1) as f64
+        } else {
+            0.0
+        };
+
+        self.start_zoom * (self.end_zoom / self.start_zoom).powf(ease_in_out(t))
+    }
+
+    /// Renders every frame and writes it as a numbered PNG image, such as `frame-0000.png`, where `frame-` is the
+    /// given prefix.  The frames are also written as an animated GIF when a GIF file name is given.
+    pub(crate) fn render(&self, prefix: &str, gif: Option<&str>) -> Result<(), Error> {
+        let mut encoder = match gif {
+            Some(filename) => Some(create_gif(filename, self.bounds)?),
+            None => None,
+        };
+
+        let mut pixels = vec![0; self.bounds.0 * self.bounds.1];
+        for frame in 0..self.frames {
+            let params = RenderParams {
+                samples: self.samples,
+                ..RenderParams::around(self.center, self.zoom(frame), self.bounds)
+            };
+            concurrent_render(&mut pixels, &params);
+            write_image(&frame_filename(prefix, frame), &pixels, &params)?;
+
+            if let Some(encoder) = encoder.as_mut() {
+                let mut gif_frame = gif::Frame::from_indexed_pixels(
+                    self.bounds.0 as u16, self.bounds.1 as u16, pixels.as_slice(), None);
+                gif_frame.delay = GIF_FRAME_DELAY;
+                encoder.write_frame(&gif_frame).map_err(gif_error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn create_gif(filename: &str, bounds: (usize, usize)) -> Result<gif::Encoder<File>, Error> {
+    if bounds.0 > u16::MAX as usize || bounds.1 > u16::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "GIF images cannot be larger than 65535x65535"));
+    }
+
+    // The rendered pixels are shades of grey, which are used as indices into a grey scale palette
+    let palette: Vec<u8> = (0..=255).flat_map(|shade| vec![shade, shade, shade]).collect();
+
+    let output = File::create(filename)?;
+    let mut encoder = gif::Encoder::new(output, bounds.0 as u16, bounds.1 as u16, &palette).map_err(gif_error)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+    Ok(encoder)
+}
+
+fn gif_error(error: gif::EncodingError) -> Error {
+    match error {
+        gif::EncodingError::Io(e) => e,
+        e => Error::other(e),
+    }
+}
+
+fn frame_filename(prefix: &str, frame: usize) -> String {
+    format!("{}{:04}.png", prefix, frame)
+}
+
+/// Smoothstep easing, which starts and ends with zero velocity
+fn ease_in_out(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(frames: usize) -> Animation {
+        Animation {
+            bounds: (40, 30),
+            center: Complex { re: -0.75, im: 0.1 },
+            start_zoom: 1.0,
+            end_zoom: 100.0,
+            frames,
+            samples: 1,
+        }
+    }
+
+    #[test]
+    fn eases_in_and_out() {
+        assert_eq!(ease_in_out(0.0), 0.0);
+        assert_eq!(ease_in_out(0.5), 0.5);
+        assert_eq!(ease_in_out(1.0), 1.0);
+        assert!(ease_in_out(0.1) < 0.1);
+        assert!(ease_in_out(0.9) > 0.9);
+    }
+
+    #[test]
+    fn zooms_from_start_to_end() {
+        let animation = animation(11);
+        assert_eq!(animation.zoom(0), 1.0);
+        assert!((animation.zoom(5) - 10.0).abs() < 1e-9);
+        assert!((animation.zoom(10) - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn single_frame_uses_start_zoom() {
+        assert_eq!(animation(1).zoom(0), 1.0);
+    }
+
+    #[test]
+    fn numbers_frames() {
+        assert_eq!(frame_filename("frames/mandel-", 7), "frames/mandel-0007.png");
+    }
+
+    #[test]
+    fn writes_frames_and_gif() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let prefix = format!("{}/frame-", directory.display());
+        let gif = format!("{}/animation.gif", directory.display());
+
+        animation(3).render(&prefix, Some(&gif)).unwrap();
+
+        for frame in 0..3 {
+            assert!(directory.join(format!("frame-{:04}.png", frame)).exists());
+        }
+        let gif = std::fs::read(&gif).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+    }
+}
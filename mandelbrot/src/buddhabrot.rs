@@ -0,0 +1,225 @@
+use num::Complex;
+use rand::{RngExt, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{in_main_cardioid, in_period2_bulb};
+
+/// The number of threads sampling points, each with its own random numbers and histograms.  It is fixed rather than
+/// the number of CPUs, as every thread gets its own seed and the image must not depend on the machine.
+const THREADS: u64 = 8;
+
+/// Renders the density of the orbits of the points outside the set, rather than the set itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buddhabrot {
+    pub bounds: (usize, usize),
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+    /// The iteration limit of every channel: one for a grey Buddhabrot, or three for the red, green and blue channels
+    /// of a Nebulabrot.  Only the orbits escaping within the limit of a channel are recorded in that channel.
+    pub limits: Vec<usize>,
+    /// The number of random points whose orbits are traced
+    pub samples: usize,
+    /// Rendering with the same seed always gives the same image, on every platform, as the random numbers come from
+    /// ChaCha8, whose output is fixed for each seed
+    pub seed: u64,
+}
+
+impl Buddhabrot {
+    /// Counts how many times the escaping orbits pass through every pixel, one histogram per channel.  The points are
+    /// sampled on several threads, each filling its own histograms, which are then added together.
+    pub fn histograms(&self) -> Vec<Vec<u32>> {
+        let partial: Vec<Vec<Vec<u32>>> = crossbeam::scope(|spawner| {
+            let threads: Vec<_> = (0..THREADS)
+                .map(|thread| {
+                    let samples = self.samples / THREADS as usize
+                        + if thread < self.samples as u64 % THREADS { 1 } else { 0 };
+                    spawner.spawn(move |_| self.sample(samples, self.random_numbers(thread)))
+                })
+                .collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        }).unwrap();
+
+        let mut histograms = vec![vec![0u32; self.bounds.0 * self.bounds.1]; self.limits.len()];
+        for thread in partial {
+            for (histogram, counts) in histograms.iter_mut().zip(thread) {
+                for (total, count) in histogram.iter_mut().zip(counts) {
+                    *total = total.saturating_add(count);
+                }
+            }
+        }
+        histograms
+    }
+
+    /// The tone mapped image, with the channels of every pixel next to each other
+    pub fn render(&self) -> Vec<u8> {
+        let channels: Vec<Vec<u8>> = self.histograms().iter().map(|histogram| tone_map(histogram)).collect();
+        (0..self.bounds.0 * self.bounds.1)
+            .flat_map(|pixel| channels.iter().map(move |channel| channel[pixel]))
+            .collect()
+    }
+
+    /// The random numbers of the thread, from a stream of their own for the seed, so that no two threads or seeds share
+    /// any of their numbers
+    fn random_numbers(&self, thread: u64) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(thread);
+        rng
+    }
+
+    fn sample(&self, samples: usize, mut rng: ChaCha8Rng) -> Vec<Vec<u32>> {
+        let mut histograms = vec![vec![0u32; self.bounds.0 * self.bounds.1]; self.limits.len()];
+        let limit = self.limits.iter().copied().max().unwrap_or(0);
+        let mut orbit = Vec::with_capacity(limit);
+
+        for _ in 0..samples {
+            // Only the points within 2 of the origin can take more than one iteration to escape
+            let c = Complex { re: rng.random_range(-2.0..2.0), im: rng.random_range(-2.0..2.0) };
+            if c.norm_sqr() > 4.0 || in_main_cardioid(c) || in_period2_bulb(c) {
+                continue;
+            }
+
+            if escaping_orbit(c, limit, &mut orbit) {
+                self.record(&orbit, &mut histograms);
+            }
+        }
+        histograms
+    }
+
+    /// Adds the orbit to the histograms of the channels whose limit it escapes within.  An orbit escaping at iteration
+    /// `n` holds the `n - 1` points before it escaped.
+    fn record(&self, orbit: &[Complex<f64>], histograms: &mut [Vec<u32>]) {
+        for (histogram, &channel_limit) in histograms.iter_mut().zip(self.limits.iter()) {
+            if orbit.len() >= channel_limit {
+                continue;
+            }
+            // The first point of every orbit is the sampled point itself, which would only add a uniform haze
+            for &z in orbit.iter().skip(1) {
+                if let Some(pixel) = self.point_to_pixel(z) {
+                    histogram[pixel] = histogram[pixel].saturating_add(1);
+                }
+            }
+        }
+    }
+
+    /// The index of the pixel the point falls in, if it falls in the image at all
+    fn point_to_pixel(&self, point: Complex<f64>) -> Option<usize> {
+        let width = self.lower_right.re - self.upper_left.re;
+        let height = self.upper_left.im - self.lower_right.im;
+        let column = (point.re - self.upper_left.re) / width * self.bounds.0 as f64;
+        let row = (self.upper_left.im - point.im) / height * self.bounds.1 as f64;
+
+        if column < 0.0 || row < 0.0 || column >= self.bounds.0 as f64 || row >= self.bounds.1 as f64 {
+            return None;
+        }
+        Some(row as usize * self.bounds.0 + column as usize)
+    }
+}
+
+/// Collects the orbit of `c` up to the point where it escapes, and returns whether it escapes within `limit`
+/// iterations
+fn escaping_orbit(c: Complex<f64>, limit: usize, orbit: &mut Vec<Complex<f64>>) -> bool {
+    orbit.clear();
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for _ in 0..limit {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0 {
+            return true;
+        }
+        orbit.push(z);
+    }
+    false
+}
+
+/// Maps the counts to shades, where the square root brings out the faint orbits next to the busiest pixels
+pub fn tone_map(histogram: &[u32]) -> Vec<u8> {
+    let max = histogram.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return vec![0; histogram.len()];
+    }
+
+    histogram.iter()
+        .map(|&count| (255.0 * (count as f64 / max as f64).sqrt()).round() as u8)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buddhabrot(limits: Vec<usize>) -> Buddhabrot {
+        Buddhabrot {
+            bounds: (40, 40),
+            upper_left: Complex { re: -2.0, im: 2.0 },
+            lower_right: Complex { re: 2.0, im: -2.0 },
+            limits,
+            samples: 20_000,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn maps_points_to_pixels() {
+        let buddhabrot = buddhabrot(vec![100]);
+        assert_eq!(buddhabrot.point_to_pixel(Complex { re: -2.0, im: 2.0 }), Some(0));
+        assert_eq!(buddhabrot.point_to_pixel(Complex { re: 0.05, im: -0.05 }), Some(20 * 40 + 20));
+        assert_eq!(buddhabrot.point_to_pixel(Complex { re: 2.0, im: 0.0 }), None);
+        assert_eq!(buddhabrot.point_to_pixel(Complex { re: 0.0, im: -2.5 }), None);
+    }
+
+    #[test]
+    fn collects_escaping_orbits_only() {
+        let mut orbit = Vec::new();
+        assert!(!escaping_orbit(Complex { re: -1.0, im: 0.0 }, 100, &mut orbit));
+        assert!(escaping_orbit(Complex { re: 1.0, im: 0.0 }, 100, &mut orbit));
+        assert_eq!(orbit, vec![Complex { re: 1.0, im: 0.0 }, Complex { re: 2.0, im: 0.0 }]);
+    }
+
+    #[test]
+    fn records_orbits_escaping_at_the_channel_limit() {
+        // 0.5 escapes at the 5th iteration, after 0.5, 0.75, 1.0625 and 1.62890625
+        let mut orbit = Vec::new();
+        assert!(escaping_orbit(Complex { re: 0.5, im: 0.0 }, 100, &mut orbit));
+        assert_eq!(orbit.len(), 4);
+
+        let buddhabrot = buddhabrot(vec![5, 4]);
+        let mut histograms = vec![vec![0u32; 40 * 40]; 2];
+        buddhabrot.record(&orbit, &mut histograms);
+        assert_eq!(histograms[0].iter().sum::<u32>(), 3);
+        assert_eq!(histograms[1].iter().sum::<u32>(), 0);
+    }
+
+    #[test]
+    fn histograms_are_reproducible() {
+        let histograms = buddhabrot(vec![100]).histograms();
+        assert_eq!(histograms.len(), 1);
+        assert!(histograms[0].iter().any(|&count| count > 0));
+        assert_eq!(buddhabrot(vec![100]).histograms(), histograms);
+        assert!(buddhabrot(vec![100]).histograms() != Buddhabrot { seed: 8, ..buddhabrot(vec![100]) }.histograms());
+    }
+
+    #[test]
+    fn nearby_seeds_share_no_random_numbers() {
+        let first = |seed, thread| Buddhabrot { seed, ..buddhabrot(vec![100]) }.random_numbers(thread).random::<u64>();
+        assert_eq!(first(7, 1), first(7, 1));
+        assert_ne!(first(7, 1), first(8, 0));
+        assert_ne!(first(7, 1), first(7, 0));
+        assert_ne!(first(7, 0), first(8, 0));
+    }
+
+    #[test]
+    fn channels_have_their_own_limits() {
+        let histograms = buddhabrot(vec![1000, 100, 10]).histograms();
+        let total = |histogram: &Vec<u32>| histogram.iter().map(|&count| count as u64).sum::<u64>();
+
+        // The channels with higher limits record every orbit the lower ones do, and the longer ones too
+        assert!(total(&histograms[0]) > total(&histograms[1]));
+        assert!(total(&histograms[1]) > total(&histograms[2]));
+        assert_eq!(buddhabrot(vec![1000, 100, 10]).render().len(), 40 * 40 * 3);
+    }
+
+    #[test]
+    fn tone_maps_to_the_busiest_pixel() {
+        assert_eq!(tone_map(&[0, 1, 4, 16]), vec![0, 64, 128, 255]);
+        assert_eq!(tone_map(&[0, 0]), vec![0, 0]);
+    }
+}
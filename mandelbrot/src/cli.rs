@@ -0,0 +1,707 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use mandelbrot::buddhabrot::Buddhabrot;
+use mandelbrot::output::Format;
+use mandelbrot::{Complex, MAX_SAMPLES, Mode, RenderParams, Trap, region_around, scene};
+
+use crate::animate::Animation;
+use crate::explore::Explorer;
+use crate::serve::TileServer;
+
+pub(crate) const USAGE: &str = "\
+Usage: mandelbrot [render] --output FILE [OPTIONS]
+       mandelbrot animate --output PREFIX --center RE,IM --end-zoom ZOOM --frames COUNT [OPTIONS]
+       mandelbrot serve [OPTIONS]
+       mandelbrot explore [OPTIONS]
+       mandelbrot buddhabrot --output FILE [OPTIONS]
+
+Render options:
+    --output FILE           image file, in the format matching its extension
+                            (png, jpg, webp, tiff, pgm, ppm, or npy and u32 for raw iteration counts)
+    --size WIDTHxHEIGHT     image size in pixels (default 1000x750)
+    --upper-left RE,IM      upper left corner of the rendered region
+    --lower-right RE,IM     lower right corner of the rendered region
+    --center RE,IM          centre of the rendered region, instead of the corners (default -0.75,0)
+    --zoom ZOOM             magnification around the centre, where 1 is 4 units wide (default 1)
+    --aa SAMPLES            anti-alias by averaging SAMPLESxSAMPLES points per pixel (default 1)
+    --iterations LIMIT      iterations after which a point is considered part of the set (default 255)
+    --mode MODE             what shades every pixel (default escape-time):
+                                escape-time   iterations before the point escapes
+                                distance      distance to the set, drawing its boundary as crisp lines
+                                relief        direction to the set, lighting the outside as a 3D surface
+                                interior      distance from the points inside the set to its boundary
+                                point-trap, line-trap, cross-trap
+                                              how close the orbit comes to a point, the horizontal line
+                                              through it, or the cross of both lines through it
+    --trap-at RE,IM         the point the orbit traps are placed at (default 0,0)
+    --equalize              spread the escape times evenly over the shades, rendering the image twice: once
+                            to count the escape times and once to shade them
+    --scene FILE            render the scene saved in a toml or json file, or embedded in a png image
+                            rendered before, instead of the size, region, anti-aliasing, iterations and mode options
+    --save-scene FILE       save the size, region, anti-aliasing, iterations, mode and equalization to a toml or
+                            json file
+    --single                render on a single thread
+    --tile-rows ROWS        render and write ROWS rows at a time, keeping memory use bounded for very large
+                            images (png, pgm and ppm only)
+
+Animate options:
+    --output PREFIX         prefix of the numbered PNG frames, such as frames/mandel-
+    --size WIDTHxHEIGHT     frame size in pixels (default 1000x750)
+    --center RE,IM          point to zoom into
+    --start-zoom ZOOM       zoom of the first frame (default 1)
+    --end-zoom ZOOM         zoom of the last frame
+    --frames COUNT          number of frames
+    --gif FILE              also write the frames as an animated GIF
+    --aa SAMPLES            anti-alias by averaging SAMPLESxSAMPLES points per pixel (default 1)
+
+Serve options:
+    --address HOST:PORT     address to serve the map tiles on (default 127.0.0.1:3000)
+    --cache DIRECTORY       keep the rendered tiles in this directory and serve them from there
+    --workers COUNT         number of tiles rendered at the same time (default number of CPUs)
+    --aa SAMPLES            anti-alias by averaging SAMPLESxSAMPLES points per pixel (default 1)
+    --iterations LIMIT      iterations of the tiles at zoom level 0, growing by 100 with every level (default 255)
+
+Explore options:
+    --center RE,IM          centre of the first view (default -0.75,0)
+    --zoom ZOOM             zoom of the first view (default 1)
+    --iterations LIMIT      iterations after which a point is considered part of the set (default 255)
+    --output PREFIX         prefix of the numbered PNG images saved with 's' (default explore-)
+    --size WIDTHxHEIGHT     size of the saved images in pixels (default 1000x750)
+    --aa SAMPLES            anti-alias the saved images by averaging SAMPLESxSAMPLES points per pixel (default 1)
+
+    Pan with the arrow keys or hjkl, zoom with + and -, change the iterations with [ and ], reset the view with r
+    and quit with q.
+
+Buddhabrot options:
+    --output FILE           image file, in the format matching its extension (png, jpg, webp, tiff or ppm)
+    --size WIDTHxHEIGHT     image size in pixels (default 1000x750)
+    --upper-left RE,IM      upper left corner of the rendered region
+    --lower-right RE,IM     lower right corner of the rendered region
+    --center RE,IM          centre of the rendered region, instead of the corners (default -0.75,0)
+    --zoom ZOOM             magnification around the centre, where 1 is 4 units wide (default 1)
+    --iterations LIMIT      only trace the orbits escaping within LIMIT iterations (default 1000), or
+    --iterations R,G,B      a limit for each of the red, green and blue channels, for a Nebulabrot
+    --samples COUNT         number of random points traced (default 50 per pixel)
+    --seed SEED             seed of the random points, the same seed giving the same image (default 0)
+
+Examples:
+    mandelbrot --output mandel.png --size 4000x3000 --upper-left -1.20,0.35 --lower-right -1.0,0.20
+    mandelbrot --output mandel.png --center -0.743643,0.131825 --zoom 500
+    mandelbrot animate --output frames/mandel- --size 640x480 --center -0.743643,0.131825 --end-zoom 10000 --frames 240 --gif mandel.gif
+    mandelbrot serve --cache tiles
+    mandelbrot explore --center -0.743643,0.131825 --zoom 100
+    mandelbrot buddhabrot --output nebula.png --iterations 5000,500,50 --samples 100000000";
+
+const DEFAULT_SIZE: (usize, usize) = (1000, 750);
+const DEFAULT_CENTER: Complex<f64> = Complex { re: -0.75, im: 0.0 };
+
+#[derive(Debug)]
+pub(crate) enum Command {
+    Render(Render),
+    Animate {
+        animation: Animation,
+        prefix: String,
+        gif: Option<String>,
+    },
+    Serve(TileServer),
+    Explore(Explorer),
+    Buddhabrot {
+        buddhabrot: Buddhabrot,
+        output: String,
+    },
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Render {
+    pub(crate) output: String,
+    pub(crate) format: Format,
+    pub(crate) params: RenderParams,
+    pub(crate) concurrent: bool,
+    pub(crate) tile_rows: Option<usize>,
+    pub(crate) save_scene: Option<String>,
+}
+
+pub(crate) fn parse_args(args: &[String]) -> Result<Command, String> {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("-h") | Some("--help") | Some("help") => Ok(Command::Help),
+        Some("render") => parse_render(&args[1..]),
+        Some("animate") => parse_animate(&args[1..]),
+        Some("serve") => parse_serve(&args[1..]),
+        Some("explore") => parse_explore(&args[1..]),
+        Some("buddhabrot") => parse_buddhabrot(&args[1..]),
+        _ => parse_render(args),
+    }
+}
+
+fn parse_render(args: &[String]) -> Result<Command, String> {
+    let scene_options = ["size", "upper-left", "lower-right", "center", "zoom", "aa", "iterations", "mode", "trap-at"];
+    let flags = Flags::parse(args, &["output", "size", "upper-left", "lower-right", "center", "zoom", "aa", "iterations",
+        "mode", "trap-at", "tile-rows", "scene", "save-scene"], &["single", "equalize"])?;
+
+    let output = flags.required("output")?.to_owned();
+    let format = Format::from_filename(&output)
+        .ok_or_else(|| format!("unsupported output format '{}', expected an extension such as .png", output))?;
+
+    let params = match flags.optional("scene") {
+        Some(scene) => {
+            if let Some(name) = scene_options.iter().find(|name| flags.optional(name).is_some()) {
+                return Err(format!("--{} cannot be combined with --scene", name));
+            }
+            scene::load(scene).map_err(|e| format!("--scene: {}", e))?
+        }
+        None => {
+            let bounds = flags.size()?;
+            let (upper_left, lower_right) = flags.region(bounds)?;
+            let params = RenderParams {
+                samples: flags.samples()?,
+                limit: flags.iterations()?,
+                mode: flags.mode()?,
+                ..RenderParams::new(bounds, upper_left, lower_right)
+            };
+            params.validate()?;
+            params
+        }
+    };
+
+    if params.samples > 1 && format.is_iterations() {
+        return Err("--aa cannot be used when writing raw iteration counts".to_owned());
+    }
+    if params.mode != Mode::EscapeTime && format.is_iterations() {
+        return Err("--mode cannot be used when writing raw iteration counts".to_owned());
+    }
+    // An equalized scene stays equalized
+    let equalize = flags.switch("equalize") || params.equalize;
+    if equalize && params.mode != Mode::EscapeTime {
+        return Err("--equalize can only be used with the escape-time mode".to_owned());
+    }
+    if equalize && format.is_iterations() {
+        return Err("--equalize cannot be used when writing raw iteration counts".to_owned());
+    }
+    let tile_rows = match flags.optional("tile-rows") {
+        None => None,
+        Some(rows) => match parse_number::<usize>(rows).map_err(|e| format!("--tile-rows: {}", e))? {
+            0 => return Err("--tile-rows: at least one row is required".to_owned()),
+            rows => Some(rows),
+        },
+    };
+    if tile_rows.is_some() && !matches!(format, Format::Png | Format::Pgm | Format::Ppm) {
+        return Err("--tile-rows can only be used with png, pgm and ppm images".to_owned());
+    }
+
+    Ok(Command::Render(Render {
+        output,
+        format,
+        params: RenderParams { equalize, ..params },
+        concurrent: !flags.switch("single"),
+        tile_rows,
+        save_scene: flags.optional("save-scene").map(|scene| scene.to_owned()),
+    }))
+}
+
+fn parse_animate(args: &[String]) -> Result<Command, String> {
+    let flags = Flags::parse(args, &["output", "size", "center", "start-zoom", "end-zoom", "frames", "gif", "aa"], &[])?;
+
+    let prefix = flags.required("output")?.to_owned();
+    let bounds = flags.size()?;
+    let center = parse_complex(flags.required("center")?).map_err(|e| format!("--center: {}", e))?;
+    let start_zoom = flags.zoom("start-zoom")?.unwrap_or(1.0);
+    let end_zoom = flags.zoom("end-zoom")?.ok_or("missing required option --end-zoom")?;
+    let frames = parse_number::<usize>(flags.required("frames")?).map_err(|e| format!("--frames: {}", e))?;
+    if frames == 0 {
+        return Err("--frames: at least one frame is required".to_owned());
+    }
+
+    Ok(Command::Animate {
+        animation: Animation { bounds, center, start_zoom, end_zoom, frames, samples: flags.samples()? },
+        prefix,
+        gif: flags.optional("gif").map(|gif| gif.to_owned()),
+    })
+}
+
+fn parse_serve(args: &[String]) -> Result<Command, String> {
+    let flags = Flags::parse(args, &["address", "cache", "workers", "aa", "iterations"], &[])?;
+
+    let workers = match flags.optional("workers") {
+        None => std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+        Some(workers) => match parse_number::<usize>(workers).map_err(|e| format!("--workers: {}", e))? {
+            0 => return Err("--workers: at least one worker is required".to_owned()),
+            workers => workers,
+        },
+    };
+
+    Ok(Command::Serve(TileServer {
+        address: flags.optional("address").unwrap_or("127.0.0.1:3000").to_owned(),
+        cache: flags.optional("cache").map(PathBuf::from),
+        workers,
+        samples: flags.samples()?,
+        limit: flags.iterations()?,
+    }))
+}
+
+fn parse_explore(args: &[String]) -> Result<Command, String> {
+    let flags = Flags::parse(args, &["center", "zoom", "iterations", "output", "size", "aa"], &[])?;
+
+    let center = match flags.optional("center") {
+        Some(center) => parse_complex(center).map_err(|e| format!("--center: {}", e))?,
+        None => DEFAULT_CENTER,
+    };
+
+    Ok(Command::Explore(Explorer {
+        center,
+        zoom: flags.zoom("zoom")?.unwrap_or(1.0),
+        limit: flags.iterations()?,
+        save_bounds: flags.size()?,
+        samples: flags.samples()?,
+        prefix: flags.optional("output").unwrap_or("explore-").to_owned(),
+    }))
+}
+
+fn parse_buddhabrot(args: &[String]) -> Result<Command, String> {
+    let flags = Flags::parse(args, &["output", "size", "upper-left", "lower-right", "center", "zoom", "iterations",
+        "samples", "seed"], &[])?;
+
+    let output = flags.required("output")?.to_owned();
+    match Format::from_filename(&output) {
+        Some(format) if !format.is_iterations() => {}
+        _ => return Err(format!("unsupported output format '{}', expected an extension such as .png", output)),
+    }
+
+    let bounds = flags.size()?;
+    let (upper_left, lower_right) = flags.region(bounds)?;
+    RenderParams::new(bounds, upper_left, lower_right).validate()?;
+
+    let limits = match flags.optional("iterations") {
+        None => vec![1000],
+        Some(limits) => limits.split(',')
+            .map(|limit| match parse_number::<usize>(limit) {
+                Ok(0) => Err("at least one iteration is required".to_owned()),
+                limit => limit,
+            })
+            .collect::<Result<Vec<usize>, String>>()
+            .map_err(|e| format!("--iterations: {}", e))?,
+    };
+    if limits.len() != 1 && limits.len() != 3 {
+        return Err(format!("--iterations: expected one limit, or three for red, green and blue, but got {}",
+                           limits.len()));
+    }
+
+    let samples = match flags.optional("samples") {
+        None => bounds.0 * bounds.1 * 50,
+        Some(samples) => parse_number(samples).map_err(|e| format!("--samples: {}", e))?,
+    };
+    let seed = match flags.optional("seed") {
+        None => 0,
+        Some(seed) => parse_number(seed).map_err(|e| format!("--seed: {}", e))?,
+    };
+
+    Ok(Command::Buddhabrot {
+        buddhabrot: Buddhabrot { bounds, upper_left, lower_right, limits, samples, seed },
+        output,
+    })
+}
+
+/// The `--name value` (or `--name=value`) options and `--name` switches given on the command line
+struct Flags {
+    values: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Flags {
+    fn parse(args: &[String], value_names: &[&str], switch_names: &[&str]) -> Result<Flags, String> {
+        let mut values = HashMap::new();
+        let mut switches = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument '{}'", arg))?;
+            let (name, inline_value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (flag, None),
+            };
+
+            if value_names.contains(&name) {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args.next()
+                        .ok_or_else(|| format!("missing value for option --{}", name))?
+                        .to_owned(),
+                };
+                if values.insert(name.to_owned(), value).is_some() {
+                    return Err(format!("option --{} given more than once", name));
+                }
+            } else if switch_names.contains(&name) && inline_value.is_none() {
+                switches.push(name.to_owned());
+            } else {
+                return Err(format!("unknown option '{}'", arg));
+            }
+        }
+
+        Ok(Flags { values, switches })
+    }
+
+    fn optional(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.optional(name).ok_or_else(|| format!("missing required option --{}", name))
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    fn size(&self) -> Result<(usize, usize), String> {
+        let bounds = match self.optional("size") {
+            None => return Ok(DEFAULT_SIZE),
+            Some(size) => parse_pair::<usize>(size, 'x').map_err(|e| format!("--size: {}", e))?,
+        };
+
+        if bounds.0 == 0 || bounds.1 == 0 {
+            return Err(format!("--size: the image must be at least 1x1 pixels, but got {}x{}", bounds.0, bounds.1));
+        }
+        Ok(bounds)
+    }
+
+    fn samples(&self) -> Result<usize, String> {
+        let samples = match self.optional("aa") {
+            None => return Ok(1),
+            Some(samples) => parse_number::<usize>(samples).map_err(|e| format!("--aa: {}", e))?,
+        };
+
+        if samples == 0 || samples > MAX_SAMPLES {
+            return Err(format!("--aa: the number of samples must be between 1 and {}, but got {}", MAX_SAMPLES, samples));
+        }
+        Ok(samples)
+    }
+
+    fn iterations(&self) -> Result<usize, String> {
+        match self.optional("iterations") {
+            None => Ok(255),
+            Some(limit) => match parse_number::<usize>(limit).map_err(|e| format!("--iterations: {}", e))? {
+                0 => Err("--iterations: at least one iteration is required".to_owned()),
+                limit => Ok(limit),
+            },
+        }
+    }
+
+    fn mode(&self) -> Result<Mode, String> {
+        let trap_at = match self.optional("trap-at") {
+            Some(point) => Some(parse_complex(point).map_err(|e| format!("--trap-at: {}", e))?),
+            None => None,
+        };
+        let point = trap_at.unwrap_or(Complex { re: 0.0, im: 0.0 });
+
+        let mode = match self.optional("mode").unwrap_or("escape-time") {
+            "escape-time" => Mode::EscapeTime,
+            "distance" => Mode::Distance,
+            "relief" => Mode::Relief,
+            "interior" => Mode::Interior,
+            "point-trap" => Mode::Trap(Trap::Point(point)),
+            "line-trap" => Mode::Trap(Trap::Line(point)),
+            "cross-trap" => Mode::Trap(Trap::Cross(point)),
+            mode => return Err(format!("--mode: unknown mode '{}'", mode)),
+        };
+
+        if trap_at.is_some() && !matches!(mode, Mode::Trap(_)) {
+            return Err("--trap-at can only be used with the point-trap, line-trap and cross-trap modes".to_owned());
+        }
+        Ok(mode)
+    }
+
+    fn zoom(&self, name: &str) -> Result<Option<f64>, String> {
+        match self.optional(name) {
+            None => Ok(None),
+            Some(value) => {
+                let zoom = parse_number::<f64>(value).map_err(|e| format!("--{}: {}", name, e))?;
+                if !zoom.is_finite() || zoom <= 0.0 {
+                    return Err(format!("--{}: the zoom must be a positive number, but got {}", name, value));
+                }
+                Ok(Some(zoom))
+            }
+        }
+    }
+
+    /// The region is given either by its corners or by its centre and zoom, keeping the aspect ratio of the image
+    fn region(&self, bounds: (usize, usize)) -> Result<(Complex<f64>, Complex<f64>), String> {
+        let corners = (self.optional("upper-left"), self.optional("lower-right"));
+        let centered = self.optional("center").is_some() || self.optional("zoom").is_some();
+
+        match corners {
+            (None, None) => {
+                let center = match self.optional("center") {
+                    Some(center) => parse_complex(center).map_err(|e| format!("--center: {}", e))?,
+                    None => DEFAULT_CENTER,
+                };
+                let zoom = self.zoom("zoom")?.unwrap_or(1.0);
+                Ok(region_around(center, zoom, bounds))
+            }
+            (Some(_), Some(_)) if centered => {
+                Err("--center and --zoom cannot be combined with --upper-left and --lower-right".to_owned())
+            }
+            (Some(upper_left), Some(lower_right)) => {
+                let upper_left = parse_complex(upper_left).map_err(|e| format!("--upper-left: {}", e))?;
+                let lower_right = parse_complex(lower_right).map_err(|e| format!("--lower-right: {}", e))?;
+                Ok((upper_left, lower_right))
+            }
+            _ => Err("--upper-left and --lower-right must be given together".to_owned()),
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, String> {
+    T::from_str(s).map_err(|_| format!("'{}' is not a valid number", s))
+}
+
+pub(crate) fn parse_pair<T: FromStr>(s: &str, separator: char) -> Result<(T, T), String> {
+    match s.find(separator) {
+        None => Err(format!("expected two numbers separated by '{}', but got '{}'", separator, s)),
+        Some(index) => {
+            let l = parse_number(&s[..index])?;
+            let r = parse_number(&s[index + 1..])?;
+            Ok((l, r))
+        }
+    }
+}
+
+pub(crate) fn parse_complex(s: &str) -> Result<Complex<f64>, String> {
+    parse_pair(s, ',').map(|(re, im)| Complex { re, im })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_owned()).collect()
+    }
+
+    fn render(line: &str) -> Result<Render, String> {
+        match parse_args(&args(line))? {
+            Command::Render(render) => Ok(render),
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn return_err_when_given_invalid_numbers() {
+        assert_eq!(parse_pair::<i32>("", ','), Err("expected two numbers separated by ',', but got ''".to_owned()));
+        assert_eq!(parse_pair::<i32>("10,", ','), Err("'' is not a valid number".to_owned()));
+        assert_eq!(parse_pair::<i32>(",10", ','), Err("'' is not a valid number".to_owned()));
+        assert_eq!(parse_pair::<i32>("10,20xy", ','), Err("'20xy' is not a valid number".to_owned()));
+    }
+
+    #[test]
+    fn return_ok_when_given_valid_numbers() {
+        assert_eq!(parse_pair::<i32>("10,20", ','), Ok((10, 20)));
+        assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Ok((0.5, 1.5)));
+    }
+
+    #[test]
+    fn return_ok_when_given_valid_complex_numbers() {
+        assert_eq!(parse_complex("1.25,-0.0625"), Ok(Complex { re: 1.25, im: -0.0625 }));
+    }
+
+    #[test]
+    fn return_err_when_given_invalid_complex_numbers() {
+        assert!(parse_complex(",-0.0625").is_err());
+    }
+
+    #[test]
+    fn parses_corners() {
+        let render = render("--output mandel.png --size 4000x3000 --upper-left -1.20,0.35 --lower-right=-1.0,0.20 --single").unwrap();
+        assert_eq!(render, Render {
+            output: "mandel.png".to_owned(),
+            format: Format::Png,
+            params: RenderParams::new((4000, 3000), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }),
+            concurrent: false,
+            tile_rows: None,
+            save_scene: None,
+        });
+    }
+
+    #[test]
+    fn parses_center_and_zoom_keeping_aspect_ratio() {
+        let render = render("render --output mandel.npy --size 400x300 --center -0.5,0 --zoom 2").unwrap();
+        assert_eq!(render.format, Format::Npy);
+        assert_eq!(render.params.upper_left, Complex { re: -1.5, im: 0.75 });
+        assert_eq!(render.params.lower_right, Complex { re: 0.5, im: -0.75 });
+        assert!(render.concurrent);
+    }
+
+    #[test]
+    fn parses_iterations() {
+        assert_eq!(render("--output mandel.png").unwrap().params.limit, 255);
+        assert_eq!(render("--output mandel.png --iterations 1000").unwrap().params.limit, 1000);
+        assert_eq!(render("--output mandel.png --iterations 0").unwrap_err(),
+                   "--iterations: at least one iteration is required");
+    }
+
+    #[test]
+    fn parses_scenes() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = directory.path().join("scene.toml");
+        let params = RenderParams { limit: 500, ..RenderParams::around(Complex { re: -0.5, im: 0.5 }, 10.0, (300, 200)) };
+        scene::save(&filename.display().to_string(), &params).unwrap();
+
+        let loaded = render_with_scene(&filename, "--save-scene copy.json").unwrap();
+        assert_eq!(loaded.params, params);
+        assert_eq!(loaded.save_scene, Some("copy.json".to_owned()));
+
+        assert_eq!(render_with_scene(&filename, "--zoom 2").unwrap_err(), "--zoom cannot be combined with --scene");
+        assert!(render("--output mandel.png --scene missing.toml").unwrap_err().starts_with("--scene: "));
+
+        // Equalized scenes are rendered equalized again
+        scene::save(&filename.display().to_string(), &RenderParams { equalize: true, ..params }).unwrap();
+        assert!(render_with_scene(&filename, "").unwrap().params.equalize);
+    }
+
+    fn render_with_scene(scene: &std::path::Path, options: &str) -> Result<Render, String> {
+        render(&format!("--output mandel.png --scene {} {}", scene.display(), options))
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(render("--output mandel.png").unwrap().params.mode, Mode::EscapeTime);
+        assert_eq!(render("--output mandel.png --mode relief").unwrap().params.mode, Mode::Relief);
+        assert_eq!(render("--output mandel.png --mode cross-trap").unwrap().params.mode,
+                   Mode::Trap(Trap::Cross(Complex { re: 0.0, im: 0.0 })));
+        assert_eq!(render("--output mandel.png --mode point-trap --trap-at -0.5,0.25").unwrap().params.mode,
+                   Mode::Trap(Trap::Point(Complex { re: -0.5, im: 0.25 })));
+
+        assert_eq!(render("--output mandel.png --mode fractal").unwrap_err(), "--mode: unknown mode 'fractal'");
+        assert_eq!(render("--output mandel.png --mode distance --trap-at 0,0").unwrap_err(),
+                   "--trap-at can only be used with the point-trap, line-trap and cross-trap modes");
+        assert_eq!(render("--output mandel.npy --mode distance").unwrap_err(),
+                   "--mode cannot be used when writing raw iteration counts");
+    }
+
+    #[test]
+    fn parses_equalize() {
+        assert!(!render("--output mandel.png").unwrap().params.equalize);
+        assert!(render("--output mandel.png --equalize --aa 2").unwrap().params.equalize);
+        assert_eq!(render("--output mandel.png --equalize --mode relief").unwrap_err(),
+                   "--equalize can only be used with the escape-time mode");
+        assert_eq!(render("--output mandel.u32 --equalize").unwrap_err(),
+                   "--equalize cannot be used when writing raw iteration counts");
+    }
+
+    #[test]
+    fn parses_anti_aliasing() {
+        assert_eq!(render("--output mandel.png").unwrap().params.samples, 1);
+        assert_eq!(render("--output mandel.png --aa 4").unwrap().params.samples, 4);
+    }
+
+    #[test]
+    fn defaults_to_whole_set() {
+        let render = render("--output mandel.png").unwrap();
+        assert_eq!(render.params, RenderParams::around(DEFAULT_CENTER, 1.0, (1000, 750)));
+    }
+
+    #[test]
+    fn reports_invalid_arguments() {
+        assert_eq!(render("--size 10x10").unwrap_err(), "missing required option --output");
+        assert_eq!(render("--output mandel.gif").unwrap_err(),
+                   "unsupported output format 'mandel.gif', expected an extension such as .png");
+        assert_eq!(render("--output mandel.png --size 0x10").unwrap_err(),
+                   "--size: the image must be at least 1x1 pixels, but got 0x10");
+        assert_eq!(render("--output mandel.png --size 10").unwrap_err(),
+                   "--size: expected two numbers separated by 'x', but got '10'");
+        assert_eq!(render("--output mandel.png --zoom -1").unwrap_err(),
+                   "--zoom: the zoom must be a positive number, but got -1");
+        assert_eq!(render("--output mandel.png --upper-left 0,1").unwrap_err(),
+                   "--upper-left and --lower-right must be given together");
+        assert_eq!(render("--output mandel.png --upper-left 0,1 --lower-right 1,0 --zoom 2").unwrap_err(),
+                   "--center and --zoom cannot be combined with --upper-left and --lower-right");
+        assert_eq!(render("--output mandel.png --upper-left 1,1 --lower-right 0,0").unwrap_err(),
+                   "the upper left corner 1+1i must be above and to the left of the lower right corner 0+0i");
+        assert_eq!(render("--output mandel.png --aa 0").unwrap_err(),
+                   "--aa: the number of samples must be between 1 and 16, but got 0");
+        assert_eq!(render("--output mandel.npy --aa 2").unwrap_err(),
+                   "--aa cannot be used when writing raw iteration counts");
+        assert_eq!(render("--output mandel.png --tile-rows 0").unwrap_err(), "--tile-rows: at least one row is required");
+        assert_eq!(render("--output mandel.jpg --tile-rows 64").unwrap_err(),
+                   "--tile-rows can only be used with png, pgm and ppm images");
+        assert_eq!(render("--output mandel.png --colour red").unwrap_err(), "unknown option '--colour'");
+        assert_eq!(render("mandel.png").unwrap_err(), "unexpected argument 'mandel.png'");
+        assert_eq!(render("--output").unwrap_err(), "missing value for option --output");
+    }
+
+    #[test]
+    fn parses_animation() {
+        let command = parse_args(&args("animate --output frames/mandel- --center -0.75,0.1 --end-zoom 100 --frames 24 --gif mandel.gif")).unwrap();
+        match command {
+            Command::Animate { animation, prefix, gif } => {
+                assert_eq!(prefix, "frames/mandel-");
+                assert_eq!(gif, Some("mandel.gif".to_owned()));
+                assert_eq!(animation.bounds, (1000, 750));
+                assert_eq!(animation.start_zoom, 1.0);
+                assert_eq!(animation.end_zoom, 100.0);
+                assert_eq!(animation.frames, 24);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert!(parse_args(&args("animate --output frames/mandel- --center -0.75,0.1 --end-zoom 100 --frames 0")).is_err());
+    }
+
+    #[test]
+    fn parses_serve() {
+        match parse_args(&args("serve --address 0.0.0.0:8080 --cache tiles --workers 2 --iterations 500")).unwrap() {
+            Command::Serve(server) => assert_eq!(server, TileServer {
+                address: "0.0.0.0:8080".to_owned(),
+                cache: Some(PathBuf::from("tiles")),
+                workers: 2,
+                samples: 1,
+                limit: 500,
+            }),
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert!(parse_args(&args("serve --workers 0")).is_err());
+    }
+
+    #[test]
+    fn parses_buddhabrot() {
+        match parse_args(&args("buddhabrot --output nebula.png --size 400x300 --iterations 5000,500,50 --seed 3")).unwrap() {
+            Command::Buddhabrot { buddhabrot, output } => {
+                assert_eq!(output, "nebula.png");
+                assert_eq!(buddhabrot.bounds, (400, 300));
+                assert_eq!(buddhabrot.limits, vec![5000, 500, 50]);
+                assert_eq!(buddhabrot.samples, 400 * 300 * 50);
+                assert_eq!(buddhabrot.seed, 3);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let error = |line: &str| parse_args(&args(line)).unwrap_err();
+        assert_eq!(error("buddhabrot --output buddha.png --iterations 100,10"),
+                   "--iterations: expected one limit, or three for red, green and blue, but got 2");
+        assert_eq!(error("buddhabrot --output buddha.png --iterations 100,0,10"),
+                   "--iterations: at least one iteration is required");
+        assert_eq!(error("buddhabrot --output buddha.npy"),
+                   "unsupported output format 'buddha.npy', expected an extension such as .png");
+    }
+
+    #[test]
+    fn parses_explore() {
+        match parse_args(&args("explore --center -0.5,0.5 --zoom 8 --iterations 1000 --size 400x300")).unwrap() {
+            Command::Explore(explorer) => assert_eq!(explorer, Explorer {
+                center: Complex { re: -0.5, im: 0.5 },
+                zoom: 8.0,
+                limit: 1000,
+                save_bounds: (400, 300),
+                samples: 1,
+                prefix: "explore-".to_owned(),
+            }),
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert_eq!(parse_args(&args("explore --zoom 0")).unwrap_err(),
+                   "--zoom: the zoom must be a positive number, but got 0");
+    }
+}
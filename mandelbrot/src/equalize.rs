@@ -0,0 +1,181 @@
+//! Histogram equalised colouring.  The linear shading of `render` spends most of its shades on the few iteration counts
+//! that hardly any point has, so instead the escape counts of the whole image are collected first, and each count is
+//! then shaded by the share of points escaping faster, spreading the points evenly over the shades.
+
+use std::ops::Range;
+
+use crate::{Progress, RenderParams, escape_time, pixel_samples, render_shaded};
+
+/// Maps escape counts to shades, so that every shade covers about as many points
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equalizer {
+    /// The shade of every escape count, where the points that never escape, at index 0, are black
+    shades: Vec<u8>,
+}
+
+impl Equalizer {
+    /// Creates the mapping from the number of sampled points escaping after each count of iterations, as collected by
+    /// `histogram`
+    pub fn new(histogram: &[u64]) -> Equalizer {
+        let escaped: u64 = histogram.iter().skip(1).sum();
+        let mut shades = vec![0; histogram.len()];
+        let mut faster = 0;
+
+        for (count, &points) in histogram.iter().enumerate().skip(1) {
+            // The points escaping first are white, and the shades darken as more points have escaped
+            shades[count] = match escaped {
+                0 => 255,
+                _ => 255 - (254.0 * faster as f64 / escaped as f64).round() as u8,
+            };
+            faster += points;
+        }
+
+        Equalizer { shades }
+    }
+
+    pub fn shade(&self, count: Option<usize>) -> u8 {
+        count.map_or(0, |count| self.shades[count])
+    }
+}
+
+/// Counts the sampled points of the image by the number of iterations they take to escape, where index 0 counts the
+/// points that never escape
+pub fn histogram(params: &RenderParams, progress: &Progress) -> Vec<u64> {
+    histogram_rows(params, 0..params.bounds.1, progress)
+}
+
+/// Like `histogram`, but only counts the points of the given rows of the image
+fn histogram_rows(params: &RenderParams, rows: Range<usize>, progress: &Progress) -> Vec<u64> {
+    let mut histogram = vec![0; params.limit + 1];
+
+    for row in rows {
+        if progress.is_cancelled() {
+            break;
+        }
+
+        for column in 0..params.bounds.0 {
+            for point in pixel_samples(params, (column, row)) {
+                histogram[escape_time(point, params.limit).unwrap_or(0)] += 1;
+            }
+        }
+
+        progress.row_done();
+    }
+
+    histogram
+}
+
+/// Like `histogram`, but collects the histograms of horizontal bands on their own threads and adds them together
+pub fn concurrent_histogram(params: &RenderParams, progress: &Progress) -> Vec<u64> {
+    histogram_in_bands(params, crate::threads(), progress)
+}
+
+/// Collects the histogram in about `count` bands, which count the same points as `histogram` however many there are
+fn histogram_in_bands(params: &RenderParams, count: usize, progress: &Progress) -> Vec<u64> {
+    let bands: Vec<Vec<u64>> = crossbeam::scope(|spawner| {
+        let threads: Vec<_> = crate::bands(0..params.bounds.1, count)
+            .map(|rows| spawner.spawn(move |_| histogram_rows(params, rows, progress)))
+            .collect();
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    }).unwrap();
+
+    let mut total = vec![0; params.limit + 1];
+    for band in bands {
+        for (total, points) in total.iter_mut().zip(band) {
+            *total += points;
+        }
+    }
+    total
+}
+
+/// Renders the region into the pixels, one byte per pixel, shading the escape counts with the equalizer
+pub fn render(pixels: &mut [u8], params: &RenderParams, equalizer: &Equalizer, progress: &Progress) {
+    render_rows(pixels, params, 0..params.bounds.1, equalizer, progress);
+}
+
+/// Like `render`, but only renders the given rows of the image, into pixels holding just those rows
+pub fn render_rows(pixels: &mut [u8],
+                   params: &RenderParams,
+                   rows: Range<usize>,
+                   equalizer: &Equalizer,
+                   progress: &Progress) {
+    render_shaded(pixels, params, rows, progress, |point| equalizer.shade(escape_time(point, params.limit)));
+}
+
+/// Like `render`, but splits the image into horizontal bands rendered on their own threads
+pub fn concurrent_render(pixels: &mut [u8], params: &RenderParams, equalizer: &Equalizer, progress: &Progress) {
+    concurrent_render_rows(pixels, params, 0..params.bounds.1, equalizer, progress);
+}
+
+/// Like `render_rows`, but splits the rows into horizontal bands rendered on their own threads
+pub fn concurrent_render_rows(pixels: &mut [u8],
+                              params: &RenderParams,
+                              rows: Range<usize>,
+                              equalizer: &Equalizer,
+                              progress: &Progress) {
+    crate::in_bands(pixels, params.bounds.0, rows, crate::threads(), |band, rows| {
+        render_rows(band, params, rows, equalizer, progress)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Complex;
+
+    use super::*;
+
+    fn params() -> RenderParams {
+        RenderParams {
+            samples: 2,
+            ..RenderParams::new((60, 40), Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 })
+        }
+    }
+
+    #[test]
+    fn spreads_points_over_the_shades() {
+        // A quarter of the points escape after 1 iteration, half after 2 and the rest after 5
+        let equalizer = Equalizer::new(&[10, 25, 50, 0, 0, 25]);
+        assert_eq!(equalizer.shade(None), 0);
+        assert_eq!(equalizer.shade(Some(1)), 255);
+        assert_eq!(equalizer.shade(Some(2)), 255 - 64);
+        assert_eq!(equalizer.shade(Some(5)), 255 - 191);
+    }
+
+    #[test]
+    fn concurrent_histogram_counts_every_sample() {
+        let progress = Progress::new();
+        let histogram = concurrent_histogram(&params(), &progress);
+        assert_eq!(histogram.iter().sum::<u64>(), 60 * 40 * 4);
+        assert_eq!(histogram, super::histogram(&params(), &Progress::new()));
+        assert_eq!(progress.rows(), 40);
+    }
+
+    #[test]
+    fn equalized_images_use_the_whole_range() {
+        let mut pixels = vec![0; 60 * 40];
+        let equalizer = Equalizer::new(&concurrent_histogram(&params(), &Progress::new()));
+        concurrent_render(&mut pixels, &params(), &equalizer, &Progress::new());
+
+        let mut single = vec![0; 60 * 40];
+        let equalizer = Equalizer::new(&histogram(&params(), &Progress::new()));
+        render(&mut single, &params(), &equalizer, &Progress::new());
+        assert!(pixels == single);
+
+        // However many bands the image is split into, which depends on the number of CPUs
+        for count in [3, 4, 5, 6, 14, 20] {
+            let histogram = histogram_in_bands(&params(), count, &Progress::new());
+            assert_eq!(Equalizer::new(&histogram), equalizer, "{} bands", count);
+
+            let mut bands = vec![0; 60 * 40];
+            crate::in_bands(&mut bands, 60, 0..40, count, |band, rows| {
+                render_rows(band, &params(), rows, &equalizer, &Progress::new())
+            });
+            assert!(bands == single, "{} bands", count);
+        }
+
+        // About as many escaping pixels are lighter than the middle shade as are darker
+        let escaping: Vec<u8> = pixels.iter().copied().filter(|&shade| shade > 0).collect();
+        let light = escaping.iter().filter(|&&shade| shade >= 128).count();
+        assert!(light * 3 > escaping.len() && light * 3 < escaping.len() * 2, "{} of {}", light, escaping.len());
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::Write as _;
+use std::io::{self, Error, Write};
+use std::path::Path;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use mandelbrot::output::write_image;
+use mandelbrot::{Complex, RenderParams, concurrent_render};
+
+/// Shown on the bottom line of the terminal, below the rendered view
+const HELP: &str = "arrows/hjkl pan  +/- zoom  [/] iterations  r reset  s save  q quit";
+
+/// The character drawn in every cell, whose upper and lower halves show two pixels on top of each other
+const HALF_BLOCK: char = '▀';
+
+/// The most iterations `]` doubles the limit to, past which every keypress would take far too long to render
+const MAX_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Explorer {
+    pub(crate) center: Complex<f64>,
+    pub(crate) zoom: f64,
+    pub(crate) limit: usize,
+    /// Size and anti-aliasing of the images saved from the current view
+    pub(crate) save_bounds: (usize, usize),
+    pub(crate) samples: usize,
+    /// Prefix of the numbered PNG images saved, such as `explore-0000.png`
+    pub(crate) prefix: String,
+}
+
+/// Leaves the alternate screen and raw mode when dropped, so that the terminal is usable again however exploring ends
+struct RawTerminal;
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Redraw,
+    Reset,
+    Save,
+    Quit,
+    Ignore,
+}
+
+impl Explorer {
+    /// Explores the set in the terminal until the user quits, restoring the terminal afterwards, even on a panic
+    pub(crate) fn run(self) -> Result<(), Error> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        let _terminal = RawTerminal;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+
+        self.explore(&mut stdout)
+    }
+
+    fn explore(mut self, stdout: &mut impl Write) -> Result<(), Error> {
+        let initial = self.clone();
+        let mut message = String::new();
+
+        loop {
+            // The last line is kept for the status, and every other line shows two rows of pixels
+            let (columns, lines) = terminal::size()?;
+            let bounds = (columns.max(1) as usize, lines.saturating_sub(1).max(1) as usize * 2);
+            let params = self.view(bounds);
+            let mut pixels = vec![0; bounds.0 * bounds.1];
+            concurrent_render(&mut pixels, &params);
+
+            stdout.write_all(draw(&pixels, bounds).as_bytes())?;
+            self.status(stdout, &message, columns)?;
+            message.clear();
+
+            loop {
+                let action = match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => self.handle(key),
+                    Event::Resize(..) => Action::Redraw,
+                    _ => Action::Ignore,
+                };
+
+                match action {
+                    Action::Redraw => break,
+                    Action::Reset => {
+                        self = initial.clone();
+                        break;
+                    }
+                    Action::Quit => return Ok(()),
+                    Action::Save => {
+                        self.status(stdout, "saving...", columns)?;
+                        message = match self.save() {
+                            Ok(filename) => format!("saved {}", filename),
+                            Err(e) => format!("failed to save the image: {}", e),
+                        };
+                        break;
+                    }
+                    Action::Ignore => {}
+                }
+            }
+        }
+    }
+
+    /// The region of the current view, rendered at the given size
+    fn view(&self, bounds: (usize, usize)) -> RenderParams {
+        RenderParams { limit: self.limit, ..RenderParams::around(self.center, self.zoom, bounds) }
+    }
+
+    /// Pans by a quarter of the width of the view and zooms by a factor of two
+    fn handle(&mut self, key: KeyEvent) -> Action {
+        let step = 1.0 / self.zoom;
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Action::Quit,
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('s') => return Action::Save,
+            KeyCode::Left | KeyCode::Char('h') => self.center.re -= step,
+            KeyCode::Right | KeyCode::Char('l') => self.center.re += step,
+            KeyCode::Up | KeyCode::Char('k') => self.center.im += step,
+            KeyCode::Down | KeyCode::Char('j') => self.center.im -= step,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.zoom *= 2.0,
+            KeyCode::Char('-') | KeyCode::Char('_') => self.zoom /= 2.0,
+            KeyCode::Char(']') if self.limit < MAX_LIMIT => self.limit = self.limit.saturating_mul(2).min(MAX_LIMIT),
+            KeyCode::Char(']') => {}
+            KeyCode::Char('[') => self.limit = (self.limit / 2).max(1),
+            KeyCode::Char('r') => return Action::Reset,
+            _ => return Action::Ignore,
+        }
+        Action::Redraw
+    }
+
+    /// Renders the current view at the save size and writes it to the first numbered PNG image that does not exist yet
+    fn save(&self) -> Result<String, Error> {
+        let filename = (0..)
+            .map(|number| format!("{}{:04}.png", self.prefix, number))
+            .find(|filename| !Path::new(filename).exists())
+            .expect("there is always a free file name");
+
+        let params = RenderParams { samples: self.samples, ..self.view(self.save_bounds) };
+        let mut pixels = vec![0; self.save_bounds.0 * self.save_bounds.1];
+        concurrent_render(&mut pixels, &params);
+        write_image(&filename, &pixels, &params)?;
+        Ok(filename)
+    }
+
+    fn status(&self, stdout: &mut impl Write, message: &str, columns: u16) -> Result<(), Error> {
+        let (_, lines) = terminal::size()?;
+        let status = match message {
+            "" => format!("{} zoom {} iterations {} | {}", self.center, self.zoom, self.limit, HELP),
+            message => message.to_owned(),
+        };
+
+        queue!(stdout, MoveTo(0, lines.saturating_sub(1)))?;
+        write!(stdout, "\x1b[0m\x1b[2K{}", status.chars().take(columns as usize).collect::<String>())?;
+        stdout.flush()
+    }
+}
+
+/// Draws the pixels as lines of half blocks in ANSI true colour, where the foreground colour is the upper pixel and the
+/// background colour the lower one.  The colours are only written when they change from the previous cell.
+fn draw(pixels: &[u8], bounds: (usize, usize)) -> String {
+    let mut frame = String::new();
+    for line in 0..bounds.1 / 2 {
+        let _ = write!(frame, "\x1b[{};1H", line + 1);
+        let mut previous = None;
+        for column in 0..bounds.0 {
+            let upper = pixels[line * 2 * bounds.0 + column];
+            let lower = pixels[(line * 2 + 1) * bounds.0 + column];
+            if previous != Some((upper, lower)) {
+                let (r, g, b) = colour(upper);
+                let _ = write!(frame, "\x1b[38;2;{};{};{}m", r, g, b);
+                let (r, g, b) = colour(lower);
+                let _ = write!(frame, "\x1b[48;2;{};{};{}m", r, g, b);
+                previous = Some((upper, lower));
+            }
+            frame.push(HALF_BLOCK);
+        }
+    }
+    frame.push_str("\x1b[0m");
+    frame
+}
+
+/// Maps the grey shade to a colour from deep blue for the points escaping fastest through pale green to red, leaving
+/// the points inside the set black.  Only the middle of the palette is used, as both of its ends are black too.
+fn colour(shade: u8) -> (u8, u8, u8) {
+    if shade == 0 {
+        return (0, 0, 0);
+    }
+
+    let t = 0.1 + 0.8 * (1.0 - shade as f64 / 255.0);
+    let r = 9.0 * (1.0 - t) * t * t * t;
+    let g = 15.0 * (1.0 - t) * (1.0 - t) * t * t;
+    let b = 8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t;
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explorer() -> Explorer {
+        Explorer {
+            center: Complex { re: -0.75, im: 0.0 },
+            zoom: 1.0,
+            limit: 255,
+            save_bounds: (40, 30),
+            samples: 1,
+            prefix: "explore-".to_owned(),
+        }
+    }
+
+    fn press(explorer: &mut Explorer, code: KeyCode) -> Action {
+        explorer.handle(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn pans_and_zooms() {
+        let mut explorer = explorer();
+        assert_eq!(press(&mut explorer, KeyCode::Char('+')), Action::Redraw);
+        assert_eq!(explorer.zoom, 2.0);
+        assert_eq!(press(&mut explorer, KeyCode::Left), Action::Redraw);
+        assert_eq!(press(&mut explorer, KeyCode::Char('k')), Action::Redraw);
+        assert_eq!(explorer.center, Complex { re: -1.25, im: 0.5 });
+
+        // Panning always moves a quarter of the width of the view
+        let width = |params: RenderParams| params.lower_right.re - params.upper_left.re;
+        assert_eq!(width(explorer.view((80, 48))) / 4.0, 0.5);
+
+        press(&mut explorer, KeyCode::Char('-'));
+        press(&mut explorer, KeyCode::Char('-'));
+        assert_eq!(explorer.zoom, 0.5);
+    }
+
+    #[test]
+    fn changes_iterations() {
+        let mut explorer = explorer();
+        press(&mut explorer, KeyCode::Char(']'));
+        assert_eq!(explorer.limit, 510);
+        for _ in 0..20 {
+            press(&mut explorer, KeyCode::Char(']'));
+        }
+        assert_eq!(explorer.limit, MAX_LIMIT);
+        for _ in 0..30 {
+            press(&mut explorer, KeyCode::Char('['));
+        }
+        assert_eq!(explorer.limit, 1);
+        assert_eq!(explorer.view((80, 48)).limit, 1);
+    }
+
+    #[test]
+    fn handles_commands() {
+        let mut explorer = explorer();
+        assert_eq!(press(&mut explorer, KeyCode::Char('s')), Action::Save);
+        assert_eq!(press(&mut explorer, KeyCode::Char('r')), Action::Reset);
+        assert_eq!(press(&mut explorer, KeyCode::Char('q')), Action::Quit);
+        assert_eq!(press(&mut explorer, KeyCode::Esc), Action::Quit);
+        assert_eq!(explorer.handle(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)), Action::Quit);
+        assert_eq!(press(&mut explorer, KeyCode::Char('x')), Action::Ignore);
+    }
+
+    #[test]
+    fn draws_two_pixels_per_cell() {
+        let pixels = [0, 0, 255, 0, 0, 0, 0, 255];
+        let frame = draw(&pixels, (4, 2));
+
+        let (r, g, b) = colour(255);
+        assert_ne!((r, g, b), colour(0));
+        assert_eq!(frame, format!(
+            "\x1b[1;1H\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m▀▀\x1b[38;2;{r};{g};{b}m\x1b[48;2;0;0;0m▀\x1b[38;2;0;0;0m\x1b[48;2;{r};{g};{b}m▀\x1b[0m",
+            r = r, g = g, b = b));
+    }
+
+    #[test]
+    fn only_the_set_is_black() {
+        assert_eq!(colour(0), (0, 0, 0));
+        for shade in 1..=255 {
+            assert_ne!(colour(shade), (0, 0, 0), "{}", shade);
+        }
+    }
+
+    #[test]
+    fn saves_numbered_images() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let explorer = Explorer { prefix: format!("{}/view-", directory.display()), ..explorer() };
+
+        assert_eq!(explorer.save().unwrap(), format!("{}/view-0000.png", directory.display()));
+        assert_eq!(explorer.save().unwrap(), format!("{}/view-0001.png", directory.display()));
+        assert_eq!(image::image_dimensions(directory.join("view-0001.png")).unwrap(), (40, 30));
+    }
+}
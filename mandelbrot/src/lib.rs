@@ -0,0 +1,648 @@
+//! Renders the Mandelbrot set.
+//!
+//! ```no_run
+//! use mandelbrot::{Complex, RenderParams, render_image};
+//!
+//! let params = RenderParams::around(Complex { re: -0.743643, im: 0.131825 }, 500.0, (800, 600));
+//! render_image(&params).save("mandel.png").unwrap();
+//! ```
+
+use std::ops::Range;
+
+use image::GrayImage;
+pub use num::Complex;
+use serde::{Deserialize, Serialize};
+
+pub use crate::orbit::{Mode, Trap};
+pub use crate::progress::Progress;
+
+pub mod buddhabrot;
+pub mod equalize;
+pub mod orbit;
+pub mod output;
+mod progress;
+pub mod scene;
+
+/// The largest number of samples per row and column of a pixel when anti-aliasing
+pub const MAX_SAMPLES: usize = 16;
+
+/// What to render: the size of the image in pixels, the region of the complex plane it covers and how much effort to
+/// put into every pixel
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderParams {
+    pub bounds: (usize, usize),
+    pub upper_left: Complex<f64>,
+    pub lower_right: Complex<f64>,
+    /// Each pixel is sampled on a grid of `samples` by `samples` points, whose shades are averaged to smooth the
+    /// edges.  A single sample per pixel disables anti-aliasing.
+    #[serde(default = "default_samples")]
+    pub samples: usize,
+    /// The number of iterations after which a point is considered to be part of the set
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// What is computed for every point to shade it, the escape time unless another mode is chosen
+    #[serde(default)]
+    pub mode: Mode,
+    /// Spread the escape times evenly over the shades, which only the escape time mode can be.  Only `render_image`
+    /// and the functions of `equalize` shade this way, while `render` and the other functions always shade linearly.
+    #[serde(default)]
+    pub equalize: bool,
+}
+
+fn default_samples() -> usize {
+    1
+}
+
+fn default_limit() -> usize {
+    255
+}
+
+impl RenderParams {
+    pub fn new(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>) -> RenderParams {
+        RenderParams {
+            bounds,
+            upper_left,
+            lower_right,
+            samples: default_samples(),
+            limit: default_limit(),
+            mode: Mode::default(),
+            equalize: false,
+        }
+    }
+
+    /// Checks that the parameters describe an image that can be rendered
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bounds.0 == 0 || self.bounds.1 == 0 {
+            return Err(format!("the image must be at least 1x1 pixels, but got {}x{}", self.bounds.0, self.bounds.1));
+        }
+        if self.upper_left.re >= self.lower_right.re || self.upper_left.im <= self.lower_right.im {
+            return Err(format!("the upper left corner {} must be above and to the left of the lower right corner {}",
+                               self.upper_left, self.lower_right));
+        }
+        if self.samples == 0 || self.samples > MAX_SAMPLES {
+            return Err(format!("the number of samples must be between 1 and {}, but got {}", MAX_SAMPLES, self.samples));
+        }
+        if self.limit == 0 {
+            return Err("the iteration limit must be at least 1".to_owned());
+        }
+        if self.equalize && self.mode != Mode::EscapeTime {
+            return Err("only the escape-time mode can be equalized".to_owned());
+        }
+        Ok(())
+    }
+
+    /// The region centred on the given point, see `region_around`
+    pub fn around(center: Complex<f64>, zoom: f64, bounds: (usize, usize)) -> RenderParams {
+        let (upper_left, lower_right) = region_around(center, zoom, bounds);
+        RenderParams::new(bounds, upper_left, lower_right)
+    }
+
+    fn pixel_count(&self) -> usize {
+        self.bounds.0 * self.bounds.1
+    }
+}
+
+#[test]
+fn test_validate() {
+    let params = RenderParams::new((100, 200), Complex { re: -1.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 });
+    assert_eq!(params.validate(), Ok(()));
+    assert!(RenderParams { bounds: (0, 200), ..params }.validate().is_err());
+    assert!(RenderParams { upper_left: params.lower_right, lower_right: params.upper_left, ..params }.validate().is_err());
+    assert!(RenderParams { samples: 0, ..params }.validate().is_err());
+    assert!(RenderParams { samples: 17, ..params }.validate().is_err());
+    assert!(RenderParams { limit: 0, ..params }.validate().is_err());
+    assert!(RenderParams { equalize: true, ..params }.validate().is_ok());
+    assert!(RenderParams { equalize: true, mode: Mode::Relief, ..params }.validate().is_err());
+}
+
+#[allow(dead_code)]
+fn square_loop(mut x: f64) {
+    loop {
+        x *= x;
+    }
+}
+
+#[allow(dead_code)]
+fn square_add_loop(c: f64) {
+    let mut x: f64 = 0.0;
+    loop {
+        x = x * x + c;
+    }
+}
+
+#[allow(dead_code)]
+fn complex_square_add_loop(c: Complex<f64>) {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    loop {
+        z += c;
+    }
+}
+
+/// The number of iterations it takes for the point to escape the circle of radius 2, or `None` if it does not escape
+/// within `limit` iterations and is considered part of the set
+pub fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
+    // Points inside the two largest components of the set never escape, so there is no need to iterate them
+    if in_main_cardioid(c) || in_period2_bulb(c) {
+        return None;
+    }
+
+    let mut z = Complex { re: 0.0, im: 0.0 };
+
+    // Brent's cycle detection: compare against a saved value of z, which is moved forward every time the number
+    // of steps since it was saved reaches the next power of two.  If z ever repeats exactly, the orbit is periodic
+    // and will never escape.
+    let mut saved = z;
+    let mut power = 1;
+    let mut steps = 0;
+
+    for i in 0..limit {
+        if z.norm_sqr() > 4.0 {
+            return Some(i);
+        }
+        z = z * z + c;
+
+        if z == saved {
+            return None;
+        }
+
+        steps += 1;
+        if steps == power {
+            saved = z;
+            power *= 2;
+            steps = 0;
+        }
+    }
+
+    None
+}
+
+fn in_main_cardioid(c: Complex<f64>) -> bool {
+    let x = c.re - 0.25;
+    let q = x * x + c.im * c.im;
+    q * (q + x) <= 0.25 * c.im * c.im
+}
+
+fn in_period2_bulb(c: Complex<f64>) -> bool {
+    let x = c.re + 1.0;
+    x * x + c.im * c.im <= 0.0625
+}
+
+#[cfg(test)]
+fn naive_escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for i in 0..limit {
+        if z.norm_sqr() > 4.0 {
+            return Some(i);
+        }
+        z = z * z + c;
+    }
+
+    None
+}
+
+#[test]
+fn interior_points_do_not_escape() {
+    assert_eq!(escape_time(Complex { re: 0.0, im: 0.0 }, 255), None);
+    assert_eq!(escape_time(Complex { re: -1.0, im: 0.0 }, 255), None);
+    assert_eq!(escape_time(Complex { re: -0.122, im: 0.745 }, 255), None);
+}
+
+#[test]
+fn exterior_points_escape_at_the_same_iteration() {
+    assert_eq!(escape_time(Complex { re: 1.0, im: 1.0 }, 255), Some(2));
+    assert_eq!(escape_time(Complex { re: 0.3, im: 0.0 }, 255), naive_escape_time(Complex { re: 0.3, im: 0.0 }, 255));
+    assert_eq!(escape_time(Complex { re: -0.75, im: 0.1 }, 255), naive_escape_time(Complex { re: -0.75, im: 0.1 }, 255));
+}
+
+#[test]
+fn escape_time_matches_naive_iteration() {
+    let bounds = (200, 150);
+    let regions = [
+        (Complex { re: -2.2, im: 1.2 }, Complex { re: 0.8, im: -1.2 }),
+        (Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }),
+        (Complex { re: -0.2, im: 1.1 }, Complex { re: 0.0, im: 0.9 }),
+    ];
+
+    for (upper_left, lower_right) in regions.iter() {
+        for row in 0..bounds.1 {
+            for column in 0..bounds.0 {
+                let point = pixel_to_point(bounds, (column, row), *upper_left, *lower_right);
+                assert_eq!(escape_time(point, 255), naive_escape_time(point, 255), "point {}", point);
+            }
+        }
+    }
+}
+
+#[test]
+fn rendered_image_is_unchanged() {
+    let bounds = (400, 300);
+    let upper_left = Complex { re: -2.2, im: 1.2 };
+    let lower_right = Complex { re: 0.8, im: -1.2 };
+
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    render(&mut pixels, &RenderParams::new(bounds, upper_left, lower_right));
+
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            expected[row * bounds.0 + column] =
+                match naive_escape_time(point, 255) {
+                    None => 0,
+                    Some(count) => 255 - count as u8,
+                }
+        }
+    }
+
+    assert!(pixels == expected);
+}
+
+/// The point on the complex plane at the upper left corner of the given pixel
+pub fn pixel_to_point(bounds: (usize, usize),
+                  pixel: (usize, usize),
+                  upper_left: Complex<f64>,
+                  lower_right: Complex<f64>,
+) -> Complex<f64> {
+    subpixel_to_point(bounds, (pixel.0 as f64, pixel.1 as f64), upper_left, lower_right)
+}
+
+/// Like `pixel_to_point`, but for a position anywhere within a pixel, such as `(2.5, 0.25)`
+pub fn subpixel_to_point(bounds: (usize, usize),
+                     position: (f64, f64),
+                     upper_left: Complex<f64>,
+                     lower_right: Complex<f64>,
+) -> Complex<f64> {
+    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    Complex {
+        re: upper_left.re + position.0 * width / bounds.0 as f64,
+        im: upper_left.im - position.1 * height / bounds.1 as f64,
+    }
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(pixel_to_point((100, 200),
+                              (25, 175),
+                              Complex { re: -1.0, im: 1.0 },
+                              Complex { re: 1.0, im: -1.0 }),
+               Complex { re: -0.5, im: -0.75 });
+}
+
+#[test]
+fn test_subpixel_to_point() {
+    assert_eq!(subpixel_to_point((100, 200),
+                                 (25.5, 175.25),
+                                 Complex { re: -1.0, im: 1.0 },
+                                 Complex { re: 1.0, im: -1.0 }),
+               Complex { re: -0.49, im: -0.7525 });
+}
+
+/// The upper left and lower right corners of the region centred on the given point.  At a zoom of 1 the region is
+/// 4 units wide, and its height follows the aspect ratio of the image.
+pub fn region_around(center: Complex<f64>,
+                 zoom: f64,
+                 bounds: (usize, usize),
+) -> (Complex<f64>, Complex<f64>) {
+    let width = 4.0 / zoom;
+    let height = width * bounds.1 as f64 / bounds.0 as f64;
+    (Complex { re: center.re - width / 2.0, im: center.im + height / 2.0 },
+     Complex { re: center.re + width / 2.0, im: center.im - height / 2.0 })
+}
+
+#[test]
+fn test_region_around() {
+    assert_eq!(region_around(Complex { re: -0.5, im: 0.0 }, 1.0, (400, 300)),
+               (Complex { re: -2.5, im: 1.5 }, Complex { re: 1.5, im: -1.5 }));
+    assert_eq!(region_around(Complex { re: 1.0, im: 1.0 }, 4.0, (100, 200)),
+               (Complex { re: 0.5, im: 2.0 }, Complex { re: 1.5, im: 0.0 }));
+}
+
+/// Renders the region into the pixels, one byte per pixel, on the current thread
+pub fn render(pixels: &mut [u8], params: &RenderParams) {
+    render_with_progress(pixels, params, &Progress::new());
+}
+
+/// Like `render`, but counts every row rendered and stops early when cancelled
+pub fn render_with_progress(pixels: &mut [u8], params: &RenderParams, progress: &Progress) {
+    render_rows(pixels, params, 0..params.bounds.1, progress);
+}
+
+/// Like `render_with_progress`, but only renders the given rows of the image, into pixels holding just those rows.
+/// Every pixel is rendered at the same points as it is when rendering the whole image.
+pub fn render_rows(pixels: &mut [u8], params: &RenderParams, rows: Range<usize>, progress: &Progress) {
+    let pixel_width = (params.lower_right.re - params.upper_left.re) / params.bounds.0 as f64;
+    render_shaded(pixels, params, rows, progress, |point| shade(point, params, pixel_width));
+}
+
+/// Renders every pixel of the rows as the rounded average of the shades of its samples
+pub(crate) fn render_shaded<F>(pixels: &mut [u8],
+                               params: &RenderParams,
+                               rows: Range<usize>,
+                               progress: &Progress,
+                               shade: F)
+    where F: Fn(Complex<f64>) -> u8 {
+    let RenderParams { bounds, samples, .. } = *params;
+    assert_eq!(pixels.len(), bounds.0 * rows.len());
+    assert!(samples > 0);
+
+    for (pixel_row, row) in rows.enumerate() {
+        if progress.is_cancelled() {
+            return;
+        }
+
+        for column in 0..bounds.0 {
+            let total: usize = pixel_samples(params, (column, row)).map(|point| shade(point) as usize).sum();
+            let count = samples * samples;
+            pixels[pixel_row * bounds.0 + column] = ((total + count / 2) / count) as u8;
+        }
+
+        progress.row_done();
+    }
+}
+
+/// The points sampled for the pixel, on a grid of `samples` by `samples` points starting from its upper left corner
+pub(crate) fn pixel_samples(params: &RenderParams, pixel: (usize, usize)) -> impl Iterator<Item = Complex<f64>> + '_ {
+    let samples = params.samples;
+    (0..samples * samples).map(move |sample| {
+        let position = (pixel.0 as f64 + (sample % samples) as f64 / samples as f64,
+                        pixel.1 as f64 + (sample / samples) as f64 / samples as f64);
+        subpixel_to_point(params.bounds, position, params.upper_left, params.lower_right)
+    })
+}
+
+/// Points in the set are black, while those outside it are lighter the faster they escape.  The other modes shade the
+/// points by their distance to the boundary, measured in pixels, or by how close their orbits come to a trap.
+fn shade(point: Complex<f64>, params: &RenderParams, pixel_width: f64) -> u8 {
+    let limit = params.limit;
+    match params.mode {
+        Mode::EscapeTime => match escape_time(point, limit) {
+            None => 0,
+            Some(count) => 255 - (count * 255 / limit) as u8,
+        },
+        Mode::Distance => match orbit::exterior_distance(point, limit) {
+            None => 0,
+            Some(exterior) => orbit::distance_shade(exterior.distance / pixel_width),
+        },
+        Mode::Relief => match orbit::exterior_distance(point, limit) {
+            None => 0,
+            Some(exterior) => orbit::relief_shade(exterior.normal),
+        },
+        Mode::Interior => match orbit::interior_distance(point, limit) {
+            None => 0,
+            Some(distance) => orbit::interior_shade(distance / pixel_width),
+        },
+        Mode::Trap(trap) => orbit::trap_shade(orbit::trap_distance(point, limit, trap)),
+    }
+}
+
+#[test]
+fn anti_aliasing_averages_samples() {
+    let bounds = (80, 60);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+
+    let params = RenderParams::new(bounds, upper_left, lower_right);
+
+    let mut aliased = vec![0; bounds.0 * bounds.1];
+    render(&mut aliased, &params);
+
+    let mut smooth = vec![0; bounds.0 * bounds.1];
+    render(&mut smooth, &RenderParams { samples: 3, ..params });
+
+    assert!(aliased != smooth);
+
+    // The first sample of every pixel is the point the pixel is rendered at without anti-aliasing
+    let mut large = vec![0; bounds.0 * 3 * bounds.1 * 3];
+    render(&mut large, &RenderParams::new((bounds.0 * 3, bounds.1 * 3), upper_left, lower_right));
+    for row in 0..bounds.1 {
+        for column in 0..bounds.0 {
+            let mut total = 0;
+            for sample_row in 0..3 {
+                for sample_column in 0..3 {
+                    total += large[(row * 3 + sample_row) * bounds.0 * 3 + column * 3 + sample_column] as usize;
+                }
+            }
+            let average = (total + 4) / 9;
+            assert!((smooth[row * bounds.0 + column] as isize - average as isize).abs() <= 1);
+        }
+    }
+}
+
+#[test]
+fn concurrent_anti_aliasing_matches_single_thread() {
+    let bounds = (90, 70);
+    let upper_left = Complex { re: -2.2, im: 1.2 };
+    let lower_right = Complex { re: 0.8, im: -1.2 };
+
+    let params = RenderParams { samples: 2, ..RenderParams::new(bounds, upper_left, lower_right) };
+
+    let mut single = vec![0; bounds.0 * bounds.1];
+    render(&mut single, &params);
+
+    let mut concurrent = vec![0; bounds.0 * bounds.1];
+    concurrent_render(&mut concurrent, &params);
+
+    assert!(single == concurrent);
+
+    // However many bands the image is split into, which depends on the number of CPUs
+    for count in [2, 3, 4, 6, 12, 16, 70] {
+        let mut bands = vec![0; bounds.0 * bounds.1];
+        in_bands(&mut bands, bounds.0, 0..bounds.1, count, |band, rows| {
+            render_rows(band, &params, rows, &Progress::new())
+        });
+        assert!(single == bands, "{} bands", count);
+    }
+}
+
+/// Records the number of iterations each pixel took to escape, or 0 for those that never escape, on the current
+/// thread.  Anti-aliasing does not apply to iteration counts.
+pub fn render_iterations(counts: &mut [u32], params: &RenderParams) {
+    render_iterations_with_progress(counts, params, &Progress::new());
+}
+
+/// Like `render_iterations`, but counts every row rendered and stops early when cancelled
+pub fn render_iterations_with_progress(counts: &mut [u32], params: &RenderParams, progress: &Progress) {
+    render_iterations_rows(counts, params, 0..params.bounds.1, progress);
+}
+
+/// Like `render_iterations_with_progress`, but only for the given rows of the image
+fn render_iterations_rows(counts: &mut [u32], params: &RenderParams, rows: Range<usize>, progress: &Progress) {
+    let RenderParams { bounds, upper_left, lower_right, limit, .. } = *params;
+    assert_eq!(counts.len(), bounds.0 * rows.len());
+
+    for (count_row, row) in rows.enumerate() {
+        if progress.is_cancelled() {
+            return;
+        }
+
+        for column in 0..bounds.0 {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            counts[count_row * bounds.0 + column] =
+                match escape_time(point, limit) {
+                    None => 0,
+                    Some(count) => count as u32,
+                }
+        }
+
+        progress.row_done();
+    }
+}
+
+#[test]
+fn iterations_match_rendered_pixels() {
+    let bounds = (100, 75);
+    let upper_left = Complex { re: -1.20, im: 0.35 };
+    let lower_right = Complex { re: -1.0, im: 0.20 };
+
+    let params = RenderParams::new(bounds, upper_left, lower_right);
+
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    concurrent_render(&mut pixels, &params);
+
+    let mut counts = vec![0; bounds.0 * bounds.1];
+    concurrent_render_iterations(&mut counts, &params);
+
+    for (pixel, count) in pixels.iter().zip(counts.iter()) {
+        let expected = if *count == 0 { 0 } else { 255 - *count as u8 };
+        assert_eq!(*pixel, expected);
+    }
+}
+
+/// Like `render`, but splits the image into horizontal bands rendered on their own threads
+pub fn concurrent_render(pixels: &mut [u8], params: &RenderParams) {
+    concurrent_render_with_progress(pixels, params, &Progress::new());
+}
+
+/// Like `concurrent_render`, but counts every row rendered and stops early when cancelled
+pub fn concurrent_render_with_progress(pixels: &mut [u8], params: &RenderParams, progress: &Progress) {
+    concurrent_render_rows(pixels, params, 0..params.bounds.1, progress);
+}
+
+/// Like `render_rows`, but splits the rows into horizontal bands rendered on their own threads
+pub fn concurrent_render_rows(pixels: &mut [u8], params: &RenderParams, rows: Range<usize>, progress: &Progress) {
+    in_bands(pixels, params.bounds.0, rows, threads(), |band, rows| render_rows(band, params, rows, progress));
+}
+
+/// Like `render_iterations`, but splits the image into horizontal bands rendered on their own threads
+pub fn concurrent_render_iterations(counts: &mut [u32], params: &RenderParams) {
+    concurrent_render_iterations_with_progress(counts, params, &Progress::new());
+}
+
+/// Like `concurrent_render_iterations`, but counts every row rendered and stops early when cancelled
+pub fn concurrent_render_iterations_with_progress(counts: &mut [u32], params: &RenderParams, progress: &Progress) {
+    in_bands(counts, params.bounds.0, 0..params.bounds.1, threads(), |band, rows| {
+        render_iterations_rows(band, params, rows, progress)
+    });
+}
+
+/// Renders the image on all threads and returns it as an image buffer, equalized when the parameters ask for it
+pub fn render_image(params: &RenderParams) -> GrayImage {
+    let mut pixels = vec![0; params.pixel_count()];
+    if params.equalize {
+        let progress = Progress::new();
+        let equalizer = equalize::Equalizer::new(&equalize::concurrent_histogram(params, &progress));
+        equalize::concurrent_render(&mut pixels, params, &equalizer, &progress);
+    } else {
+        concurrent_render(&mut pixels, params);
+    }
+    GrayImage::from_raw(params.bounds.0 as u32, params.bounds.1 as u32, pixels)
+        .expect("the pixels match the size of the image")
+}
+
+/// The number of threads rendering concurrently, one for every CPU
+pub(crate) fn threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |count| count.get())
+}
+
+/// Splits the buffer, holding the given rows of `width` items each, into about `count` horizontal bands and renders
+/// each band on its own thread.  Every band is told which rows of the image it holds rather than given a region of its
+/// own, so that its pixels are rendered at exactly the same points however many bands there are.
+pub(crate) fn in_bands<T, F>(buffer: &mut [T], width: usize, rows: Range<usize>, count: usize, render: F)
+    where T: Send,
+          F: Fn(&mut [T], Range<usize>) + Sync {
+    assert_eq!(buffer.len(), width * rows.len());
+    let render = &render;
+    crossbeam::scope(|spawner| {
+        let mut rest = buffer;
+        for band_rows in bands(rows, count) {
+            let (band, others) = std::mem::take(&mut rest).split_at_mut(width * band_rows.len());
+            rest = others;
+            spawner.spawn(move |_| render(band, band_rows));
+        }
+    }).unwrap();
+}
+
+/// The rows split into about `count` bands of consecutive rows
+pub(crate) fn bands(rows: Range<usize>, count: usize) -> impl Iterator<Item = Range<usize>> {
+    let rows_per_band = rows.len() / count + 1;
+    let end = rows.end;
+    rows.step_by(rows_per_band).map(move |top| top..(top + rows_per_band).min(end))
+}
+
+#[test]
+fn counts_rendered_rows() {
+    let params = RenderParams::new((40, 30), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 });
+
+    let progress = Progress::new();
+    let mut pixels = vec![0; 40 * 30];
+    concurrent_render_with_progress(&mut pixels, &params, &progress);
+    assert_eq!(progress.rows(), 30);
+
+    let progress = Progress::new();
+    let mut counts = vec![0; 40 * 30];
+    render_iterations_with_progress(&mut counts, &params, &progress);
+    assert_eq!(progress.rows(), 30);
+}
+
+#[test]
+fn cancelled_render_stops_early() {
+    let params = RenderParams::new((40, 30), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 });
+
+    let progress = Progress::new();
+    progress.cancel();
+    let mut pixels = vec![7; 40 * 30];
+    concurrent_render_with_progress(&mut pixels, &params, &progress);
+
+    assert_eq!(progress.rows(), 0);
+    assert!(pixels.iter().all(|&pixel| pixel == 7));
+}
+
+#[test]
+fn test_render_image() {
+    let params = RenderParams::new((40, 30), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 });
+
+    let mut pixels = vec![0; 40 * 30];
+    render(&mut pixels, &params);
+
+    let image = render_image(&params);
+    assert_eq!(image.dimensions(), (40, 30));
+    assert!(image.into_raw() == pixels);
+}
+
+#[test]
+fn renders_every_mode_concurrently() {
+    let modes = [Mode::Distance, Mode::Relief, Mode::Interior, Mode::Trap(Trap::Cross(Complex { re: 0.0, im: 0.0 }))];
+    let escape_time = render_image(&RenderParams::new((60, 40), Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 }));
+
+    for &mode in modes.iter() {
+        let params = RenderParams {
+            mode,
+            ..RenderParams::new((60, 40), Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 })
+        };
+
+        let mut single = vec![0; 60 * 40];
+        render(&mut single, &params);
+        let mut concurrent = vec![0; 60 * 40];
+        concurrent_render(&mut concurrent, &params);
+
+        assert!(single == concurrent, "{:?}", mode);
+        assert!(single != escape_time.as_raw()[..], "{:?}", mode);
+
+        for count in [3, 4, 6, 16] {
+            let mut bands = vec![0; 60 * 40];
+            in_bands(&mut bands, 60, 0..40, count, |band, rows| render_rows(band, &params, rows, &Progress::new()));
+            assert!(single == bands, "{:?} in {} bands", mode, count);
+        }
+    }
+}
@@ -1,199 +1,128 @@
 use std::env;
-use std::fs::File;
-use std::str::FromStr;
+use std::io::Error;
+use std::sync::Arc;
 
-use image::ColorType;
-use image::png::PNGEncoder;
-use num::Complex;
+use mandelbrot::equalize::{self, Equalizer};
+use mandelbrot::output::{write_channels, write_image, write_iterations};
+use mandelbrot::{
+    Progress, concurrent_render_iterations_with_progress, concurrent_render_with_progress,
+    render_iterations_with_progress, render_with_progress, scene,
+};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() != 6 {
-        show_usage(&args[0]);
-    }
-
-    if !args[5].eq_ignore_ascii_case("CONCURRENT") && !args[5].eq_ignore_ascii_case("SINGLE") {
-        show_usage(&args[0]);
-    }
-
-    let bounds = parse_pair(&args[2], 'x')
-        .expect("invalid image dimensions");
-
-    let upper_left = parse_complex(&args[3])
-        .expect("invalid upper left point");
-
-    let lower_right = parse_complex(&args[4])
-        .expect("invalid lower right point");
-
-    let mut pixels = vec![0; bounds.0 * bounds.1];
-
-    if args[5].eq_ignore_ascii_case("CONCURRENT") {
-        concurrent_render(&mut pixels, bounds, upper_left, lower_right);
-    } else {
-        render(&mut pixels, bounds, upper_left, lower_right);
-    }
+use crate::cli::{Command, Render, USAGE};
 
-    write_image(&args[1], &pixels, bounds)
-        .expect("failed to create PNG image")
-}
+mod animate;
+mod cli;
+mod explore;
+mod report;
+mod serve;
+mod tiled;
 
-fn show_usage(program_name: &String) {
-    eprintln!("Usage: {} FILE PIXELS UPPER-LEFT LOWER-RIGHT (SINGLE|CONCURRENT)", program_name);
-    eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1.0,0.20 SINGLE", program_name);
-    std::process::exit(1);
-}
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command = match cli::parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Run 'mandelbrot --help' for usage");
+            std::process::exit(1);
+        }
+    };
+
+    let result = match command {
+        Command::Render(options) => render_with_report(&options),
+        Command::Animate { animation, prefix, gif } => animation.render(&prefix, gif.as_deref()),
+        Command::Serve(server) => server.run(),
+        Command::Explore(explorer) => explorer.run(),
+        Command::Buddhabrot { buddhabrot, output } => {
+            write_channels(&output, &buddhabrot.render(), buddhabrot.bounds, buddhabrot.limits.len())
+        }
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    };
 
-fn square_loop(mut x: f64) {
-    loop {
-        x = x * x;
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
 }
 
-fn square_add_loop(c: f64) {
-    let mut x: f64 = 0.0;
-    loop {
-        x = x * x + c;
-    }
-}
+/// Renders the image showing its progress.  When cancelled with Ctrl-C, the rows rendered so far are still written
+/// and the rest of the image is left black, unless an equalised image is cancelled before any pixel is shaded.
+fn render_with_report(options: &Render) -> Result<(), Error> {
+    // Equalised images are rendered twice, once to collect the histogram and once to shade the pixels
+    let rows = options.params.bounds.1 * if options.params.equalize { 2 } else { 1 };
+    let progress = Arc::new(Progress::new());
+    report::cancel_on_interrupt(&progress)?;
 
-fn complex_square_add_loop(c: Complex<f64>) {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    loop {
-        z = z + c;
-    }
-}
+    let written = report::show_progress(&progress, rows, || render_to_file(options, &progress))?;
 
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+    if progress.is_cancelled() {
+        if written {
+            eprintln!("cancelled after {} of {} rows, wrote the partial image to '{}'",
+                      progress.rows().min(rows), rows, options.output);
+        } else {
+            eprintln!("cancelled after {} of {} rows, before shading any pixel, so no image was written",
+                      progress.rows().min(rows), rows);
         }
-        z = z * z + c;
+        std::process::exit(130);
     }
-
-    None
+    Ok(())
 }
 
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
-    match s.find(separator) {
-        None => None,
-        Some(index) => {
-            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-                (Ok(l), Ok(r)) => Some((l, r)),
-                _ => None
-            }
-        }
+/// Renders the image and writes it, returning whether it was written
+fn render_to_file(options: &Render, progress: &Progress) -> Result<bool, Error> {
+    if let Some(filename) = &options.save_scene {
+        scene::save(filename, &options.params)?;
     }
-}
-
-#[test]
-fn return_none_when_given_invalid_numbers() {
-    assert_eq!(parse_pair::<i32>("", ','), None);
-    assert_eq!(parse_pair::<i32>("10,", ','), None);
-    assert_eq!(parse_pair::<i32>(",10", ','), None);
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-}
-
-#[test]
-fn return_some_when_given_valid_numbers() {
-    assert_eq!(parse_pair::<i32>("10,20", ','), Some((10, 20)));
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
-}
-
 
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None
+    if let Some(tile_rows) = options.tile_rows {
+        return tiled::render_tiled(options, tile_rows, progress);
     }
-}
 
-#[test]
-fn return_some_when_given_valid_complex_numbers() {
-    assert_eq!(parse_complex("1.25,-0.0625"), Some(Complex { re: 1.25, im: -0.0625 }));
-}
+    let params = &options.params;
+    let bounds = params.bounds;
 
-#[test]
-fn return_none_when_given_invalid_complex_numbers() {
-    assert_eq!(parse_complex(",-0.0625"), None);
-}
+    if options.format.is_iterations() {
+        let mut counts = vec![0; bounds.0 * bounds.1];
 
-fn pixel_to_point(bounds: (usize, usize),
-                  pixel: (usize, usize),
-                  upper_left: Complex<f64>,
-                  lower_right: Complex<f64>,
-) -> Complex<f64> {
-    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
-    Complex {
-        re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
-    }
-}
+        if options.concurrent {
+            concurrent_render_iterations_with_progress(&mut counts, params, progress);
+        } else {
+            render_iterations_with_progress(&mut counts, params, progress);
+        }
 
-#[test]
-fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100, 200),
-                              (25, 175),
-                              Complex { re: -1.0, im: 1.0 },
-                              Complex { re: 1.0, im: -1.0 }),
-               Complex { re: -0.5, im: -0.75 });
-}
+        write_iterations(&options.output, &counts, bounds)?;
+    } else {
+        let mut pixels = vec![0; bounds.0 * bounds.1];
+
+        if params.equalize {
+            let histogram = if options.concurrent {
+                equalize::concurrent_histogram(params, progress)
+            } else {
+                equalize::histogram(params, progress)
+            };
+            // The histogram is incomplete, and no pixel would be shaded
+            if progress.is_cancelled() {
+                return Ok(false);
+            }
 
-fn render(pixels: &mut [u8],
-          bounds: (usize, usize),
-          upper_left: Complex<f64>,
-          lower_right: Complex<f64>,
-) {
-    assert_eq!(pixels.len(), bounds.0 * bounds.1);
-
-    for row in 0..bounds.1 {
-        for column in 0..bounds.0 {
-            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-            pixels[row * bounds.0 + column] =
-                match escape_time(point, 255) {
-                    None => 0,
-                    Some(count) => 255 - count as u8,
-                }
+            let equalizer = Equalizer::new(&histogram);
+            if options.concurrent {
+                equalize::concurrent_render(&mut pixels, params, &equalizer, progress);
+            } else {
+                equalize::render(&mut pixels, params, &equalizer, progress);
+            }
+        } else if options.concurrent {
+            concurrent_render_with_progress(&mut pixels, params, progress);
+        } else {
+            render_with_progress(&mut pixels, params, progress);
         }
-    }
-}
 
-fn concurrent_render(pixels: &mut [u8],
-                     bounds: (usize, usize),
-                     upper_left: Complex<f64>,
-                     lower_right: Complex<f64>,
-) {
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
-
-    {
-        let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-                let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
-
-                spawner.spawn(move |_| {
-                    render(band, band_bounds, band_upper_left, band_lower_right);
-                });
-            }
-        }).unwrap();
+        write_image(&options.output, &pixels, params)?;
     }
-}
-
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error> {
-    // The following can be expressed in shorter form using the ? operator
-    // let output = match File::create(filename) {
-    //     Ok(f) => f,
-    //     Err(e) => { return Err(e); }
-    // };
-    let output = File::create(filename)?;
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels, bounds.0 as u32, bounds.1 as u32, ColorType::Gray(8))?;
-    Ok(())
+    Ok(true)
 }
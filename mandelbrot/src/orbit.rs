@@ -0,0 +1,227 @@
+use num::Complex;
+use serde::{Deserialize, Serialize};
+
+/// The radius beyond which an orbit is considered to have escaped when estimating distances.  It is much larger than
+/// the radius of 2 used for escape times, as the estimates are only accurate far from the set.
+const ESCAPE_RADIUS: f64 = 1000.0;
+
+/// The number of Newton steps taken to find a point of an attracting cycle exactly
+const NEWTON_STEPS: usize = 16;
+
+/// What is computed for every point, which in turn decides its shade
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// The number of iterations before the point escapes
+    #[default]
+    EscapeTime,
+    /// The estimated distance from the points outside the set to its boundary, drawing the boundary as thin lines
+    Distance,
+    /// The direction of the distance estimate, lighting the outside of the set as a 3D surface
+    Relief,
+    /// The estimated distance from the points inside the set to its boundary
+    Interior,
+    /// How close the orbit of the point comes to the trap
+    Trap(Trap),
+}
+
+/// A shape that orbits are measured against
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trap {
+    Point(Complex<f64>),
+    /// The horizontal line through the point
+    Line(Complex<f64>),
+    /// The horizontal and vertical lines through the point
+    Cross(Complex<f64>),
+}
+
+impl Trap {
+    fn distance(&self, z: Complex<f64>) -> f64 {
+        match *self {
+            Trap::Point(point) => (z - point).norm(),
+            Trap::Line(point) => (z.im - point.im).abs(),
+            Trap::Cross(point) => (z.re - point.re).abs().min((z.im - point.im).abs()),
+        }
+    }
+}
+
+/// The estimated distance from a point outside the set to the set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exterior {
+    pub distance: f64,
+    /// The direction away from the set, of length 1
+    pub normal: Complex<f64>,
+}
+
+/// Estimates the distance from `c` to the set from the derivative of its orbit, or returns `None` when `c` does not
+/// escape within `limit` iterations
+pub fn exterior_distance(c: Complex<f64>, limit: usize) -> Option<Exterior> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut dz = Complex { re: 0.0, im: 0.0 };
+    for _ in 0..limit {
+        dz = 2.0 * z * dz + 1.0;
+        z = z * z + c;
+        if z.norm_sqr() > ESCAPE_RADIUS * ESCAPE_RADIUS {
+            let norm = z.norm();
+            let normal = z / dz;
+            return Some(Exterior {
+                distance: 0.5 * norm * norm.ln() / dz.norm(),
+                normal: normal / normal.norm(),
+            });
+        }
+    }
+    None
+}
+
+/// Estimates the distance from `c` to the boundary of the set when `c` is inside the set, from the attracting cycle
+/// its orbit settles into.  Returns `None` when `c` escapes, or when no cycle is found within `limit` iterations.
+pub fn interior_distance(c: Complex<f64>, limit: usize) -> Option<f64> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    for _ in 0..limit {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0 {
+            return None;
+        }
+    }
+
+    let period = period(z, c, limit)?;
+
+    // Newton's method on f^p(w) - w, starting from the approximate point of the cycle
+    let mut w = z;
+    for _ in 0..NEWTON_STEPS {
+        let (mut fw, mut dw) = (w, Complex { re: 1.0, im: 0.0 });
+        for _ in 0..period {
+            dw = 2.0 * fw * dw;
+            fw = fw * fw + c;
+        }
+        let step = (fw - w) / (dw - 1.0);
+        w -= step;
+        if step.norm_sqr() < 1e-24 {
+            break;
+        }
+    }
+
+    // The derivatives of f^p with respect to z and c around the cycle
+    let mut z = w;
+    let one = Complex { re: 1.0, im: 0.0 };
+    let (mut dz, mut dzdz) = (one, Complex { re: 0.0, im: 0.0 });
+    let (mut dc, mut dcdz) = (Complex { re: 0.0, im: 0.0 }, Complex { re: 0.0, im: 0.0 });
+    for _ in 0..period {
+        dcdz = 2.0 * (z * dcdz + dz * dc);
+        dzdz = 2.0 * (dz * dz + z * dzdz);
+        dc = 2.0 * z * dc + 1.0;
+        dz = 2.0 * z * dz;
+        z = z * z + c;
+    }
+
+    if dz.norm_sqr() >= 1.0 {
+        return None;
+    }
+    Some((1.0 - dz.norm_sqr()) / (dcdz + dzdz * dc / (one - dz)).norm())
+}
+
+/// The length of the cycle the orbit has settled into at `z`
+fn period(z: Complex<f64>, c: Complex<f64>, limit: usize) -> Option<usize> {
+    let mut w = z;
+    for period in 1..=limit {
+        w = w * w + c;
+        if (w - z).norm_sqr() < 1e-20 {
+            return Some(period);
+        }
+    }
+    None
+}
+
+/// The closest the orbit of `c` comes to the trap, before it escapes or within `limit` iterations
+pub fn trap_distance(c: Complex<f64>, limit: usize, trap: Trap) -> f64 {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut closest = f64::INFINITY;
+    for _ in 0..limit {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0 {
+            break;
+        }
+        closest = closest.min(trap.distance(z));
+    }
+    closest
+}
+
+/// Dark for distances below a pixel, fading to white a few pixels away, so that the boundary is drawn as a thin line
+pub(crate) fn distance_shade(pixels: f64) -> u8 {
+    (255.0 * pixels.min(1.0).powf(0.25)) as u8
+}
+
+/// Dark at the boundary and lighter deeper inside the set, fading over 32 pixels so that the shape of every
+/// component shows
+pub(crate) fn interior_shade(pixels: f64) -> u8 {
+    (255.0 * (pixels / 32.0).min(1.0).sqrt()) as u8
+}
+
+/// Lights the surface whose slope is given by the normal from the upper left, as if it were 1.5 times as high as the
+/// light
+pub(crate) fn relief_shade(normal: Complex<f64>) -> u8 {
+    let light = Complex::from_polar(1.0, std::f64::consts::FRAC_PI_4 * 3.0);
+    let height = 1.5;
+    let brightness = (normal.re * light.re + normal.im * light.im + height) / (1.0 + height);
+    (255.0 * brightness.clamp(0.0, 1.0)) as u8
+}
+
+/// Bright where the orbit passes through the trap, fading to black further away
+pub(crate) fn trap_shade(distance: f64) -> u8 {
+    (255.0 * (-4.0 * distance).exp()) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_exterior_distance() {
+        assert_eq!(exterior_distance(Complex { re: -0.5, im: 0.0 }, 1000), None);
+
+        // The tip of the set is at -2, so the points to its left are about as far as they are from there, in the
+        // direction pointing away from the set
+        for &re in [-2.1, -2.5, -3.0].iter() {
+            let exterior = exterior_distance(Complex { re, im: 0.0 }, 1000).unwrap();
+            assert!(exterior.distance > (-2.0 - re) / 4.0 && exterior.distance < (-2.0 - re) * 2.0, "{}", re);
+            assert!((exterior.normal - Complex { re: -1.0, im: 0.0 }).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn estimates_interior_distance() {
+        assert_eq!(interior_distance(Complex { re: 1.0, im: 0.0 }, 1000), None);
+
+        // The centre of the main cardioid is 0.25 away from its cusp, and the centre of the period 2 bulb is 0.25 away
+        // from its edge
+        let center = interior_distance(Complex { re: 0.0, im: 0.0 }, 1000).unwrap();
+        assert!(center > 0.25 / 4.0 && center < 0.25 * 4.0, "{}", center);
+        let bulb = interior_distance(Complex { re: -1.0, im: 0.0 }, 1000).unwrap();
+        assert!(bulb > 0.25 / 4.0 && bulb < 0.25 * 4.0, "{}", bulb);
+
+        // Closer to the boundary, the distance gets smaller
+        assert!(interior_distance(Complex { re: 0.2, im: 0.0 }, 1000).unwrap() < center);
+    }
+
+    #[test]
+    fn measures_orbit_traps() {
+        let origin = Complex { re: 0.0, im: 0.0 };
+        // The orbit of -1 alternates between -1 and 0
+        let c = Complex { re: -1.0, im: 0.0 };
+        assert_eq!(trap_distance(c, 100, Trap::Point(origin)), 0.0);
+        assert_eq!(trap_distance(c, 100, Trap::Point(Complex { re: 0.0, im: 0.5 })), 0.5);
+        assert_eq!(trap_distance(c, 100, Trap::Line(Complex { re: 3.0, im: 0.25 })), 0.25);
+        assert_eq!(trap_distance(c, 100, Trap::Cross(Complex { re: -0.75, im: 2.0 })), 0.25);
+
+        // Escaping orbits are only measured until they escape
+        assert_eq!(trap_distance(Complex { re: 3.0, im: 0.0 }, 100, Trap::Point(origin)), f64::INFINITY);
+    }
+
+    #[test]
+    fn reads_modes_from_json() {
+        assert_eq!(serde_json::from_str::<Mode>("\"relief\"").unwrap(), Mode::Relief);
+        assert_eq!(serde_json::from_str::<Mode>("{\"trap\":{\"cross\":[0.5,-0.5]}}").unwrap(),
+                   Mode::Trap(Trap::Cross(Complex { re: 0.5, im: -0.5 })));
+    }
+}
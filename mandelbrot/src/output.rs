@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::codecs::tiff::TiffEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder, ImageError};
+
+use crate::{RenderParams, scene};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Pgm,
+    Ppm,
+    /// NumPy array of `u32` iteration counts
+    Npy,
+    /// Little endian `u32` iteration counts, row by row, without a header
+    U32,
+}
+
+impl Format {
+    pub fn from_filename(filename: &str) -> Option<Format> {
+        let extension = Path::new(filename).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "webp" => Some(Format::WebP),
+            "tif" | "tiff" => Some(Format::Tiff),
+            "pgm" => Some(Format::Pgm),
+            "ppm" => Some(Format::Ppm),
+            "npy" => Some(Format::Npy),
+            "u32" => Some(Format::U32),
+            _ => None
+        }
+    }
+
+    /// Whether the format holds the raw iteration counts rather than an image
+    pub fn is_iterations(&self) -> bool {
+        matches!(self, Format::Npy | Format::U32)
+    }
+}
+
+/// Writes the grey scale pixels rendered with the given parameters as an image, in the format matching the file
+/// extension.  PNG images also hold the parameters, so that they can be rendered again.
+pub fn write_image(filename: &str, pixels: &[u8], params: &RenderParams) -> Result<(), Error> {
+    let format = match Format::from_filename(filename) {
+        Some(format) if !format.is_iterations() => format,
+        _ => return Err(unsupported_format(filename)),
+    };
+
+    let output = BufWriter::new(File::create(filename)?);
+    let (width, height) = (params.bounds.0 as u32, params.bounds.1 as u32);
+    let result = match format {
+        Format::Png => {
+            let mut writer = png_encoder(output, params)?.write_header()?;
+            writer.write_image_data(pixels)?;
+            return Ok(writer.finish()?);
+        }
+        Format::Jpeg => JpegEncoder::new(output).write_image(pixels, width, height, ExtendedColorType::L8),
+        Format::WebP => WebPEncoder::new_lossless(output).write_image(pixels, width, height, ExtendedColorType::L8),
+        Format::Tiff => TiffEncoder::new(output).write_image(pixels, width, height, ExtendedColorType::L8),
+        Format::Pgm => PnmEncoder::new(output)
+            .with_subtype(PnmSubtype::Graymap(SampleEncoding::Binary))
+            .write_image(pixels, width, height, ExtendedColorType::L8),
+        Format::Ppm => {
+            // Pixmaps only hold colour images, so every shade of grey is repeated for the red, green and blue channels
+            let rgb: Vec<u8> = pixels.iter().flat_map(|&shade| [shade, shade, shade]).collect();
+            PnmEncoder::new(output)
+                .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                .write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+        }
+        Format::Npy | Format::U32 => unreachable!(),
+    };
+
+    result.map_err(image_error)
+}
+
+/// Writes pixels with one (grey) or three (red, green and blue) channels each, without any parameters, for images
+/// that cannot be rendered again from a scene
+pub fn write_channels(filename: &str, pixels: &[u8], bounds: (usize, usize), channels: usize) -> Result<(), Error> {
+    match Format::from_filename(filename) {
+        Some(format) if !format.is_iterations() => {}
+        _ => return Err(unsupported_format(filename)),
+    }
+
+    let colour = if channels == 3 { ExtendedColorType::Rgb8 } else { ExtendedColorType::L8 };
+    image::save_buffer(filename, pixels, bounds.0 as u32, bounds.1 as u32, colour).map_err(image_error)
+}
+
+/// Encodes the grey scale pixels as a PNG image in memory
+pub fn encode_png(pixels: &[u8], params: &RenderParams) -> Result<Vec<u8>, Error> {
+    let mut png = Vec::new();
+    let mut writer = png_encoder(&mut png, params)?.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+/// A grey scale PNG encoder, which embeds the parameters as a text chunk
+fn png_encoder<W: Write>(output: W, params: &RenderParams) -> Result<png::Encoder<'static, W>, Error> {
+    let mut encoder = png::Encoder::new(output, params.bounds.0 as u32, params.bounds.1 as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_text_chunk(scene::PNG_KEYWORD.to_owned(), scene::to_json(params))?;
+    Ok(encoder)
+}
+
+/// Writes an image a few rows at a time, so that the whole image never needs to be held in memory.  Only the PNG, PGM
+/// and PPM formats can be written this way.
+pub enum RowWriter {
+    Png(Box<png::StreamWriter<'static, BufWriter<File>>>),
+    Pnm {
+        output: BufWriter<File>,
+        rgb: bool,
+    },
+}
+
+impl RowWriter {
+    pub fn create(filename: &str, params: &RenderParams) -> Result<RowWriter, Error> {
+        let format = Format::from_filename(filename);
+        if !matches!(format, Some(Format::Png) | Some(Format::Pgm) | Some(Format::Ppm)) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("only png, pgm and ppm images can be rendered in tiles: '{}'", filename)));
+        }
+
+        let mut output = BufWriter::new(File::create(filename)?);
+        if format == Some(Format::Png) {
+            let writer = png_encoder(output, params)?.write_header()?;
+            return Ok(RowWriter::Png(Box::new(writer.into_stream_writer()?)));
+        }
+
+        let rgb = format == Some(Format::Ppm);
+        write!(output, "{}\n{} {}\n255\n", if rgb { "P6" } else { "P5" }, params.bounds.0, params.bounds.1)?;
+        Ok(RowWriter::Pnm { output, rgb })
+    }
+
+    pub fn write_rows(&mut self, pixels: &[u8]) -> Result<(), Error> {
+        match self {
+            RowWriter::Png(stream) => stream.write_all(pixels),
+            RowWriter::Pnm { output, rgb: false } => output.write_all(pixels),
+            RowWriter::Pnm { output, rgb: true } => {
+                for &shade in pixels {
+                    output.write_all(&[shade, shade, shade])?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            RowWriter::Png(stream) => Ok(stream.finish()?),
+            RowWriter::Pnm { mut output, .. } => output.flush(),
+        }
+    }
+}
+
+/// Writes the iteration counts in the raw format matching the file extension, so that these can be recoloured
+/// without rendering them again
+pub fn write_iterations(filename: &str, counts: &[u32], bounds: (usize, usize)) -> Result<(), Error> {
+    let format = match Format::from_filename(filename) {
+        Some(format) if format.is_iterations() => format,
+        _ => return Err(unsupported_format(filename)),
+    };
+
+    let mut output = BufWriter::new(File::create(filename)?);
+    if format == Format::Npy {
+        output.write_all(&npy_header(bounds))?;
+    }
+    for count in counts {
+        output.write_all(&count.to_le_bytes())?;
+    }
+    output.flush()
+}
+
+/// The header of a version 1.0 NumPy file holding a two dimensional array of little endian `u32`, one row per line
+/// of pixels.  The header is padded with spaces so that the data that follows is aligned to 64 bytes.
+fn npy_header(bounds: (usize, usize)) -> Vec<u8> {
+    let mut dictionary = format!("{{'descr': '<u4', 'fortran_order': False, 'shape': ({}, {}), }}", bounds.1, bounds.0);
+    let unpadded = 10 + dictionary.len() + 1;
+    dictionary.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    dictionary.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dictionary.len() as u16).to_le_bytes());
+    header.extend_from_slice(dictionary.as_bytes());
+    header
+}
+
+fn unsupported_format(filename: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("unsupported output format: '{}'", filename))
+}
+
+fn image_error(error: ImageError) -> Error {
+    match error {
+        ImageError::IoError(e) => e,
+        e => Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Complex;
+
+    use super::*;
+
+    fn params(bounds: (usize, usize)) -> RenderParams {
+        RenderParams::new(bounds, Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 })
+    }
+
+    #[test]
+    fn format_from_file_extension() {
+        assert_eq!(Format::from_filename("mandel.png"), Some(Format::Png));
+        assert_eq!(Format::from_filename("mandel.JPG"), Some(Format::Jpeg));
+        assert_eq!(Format::from_filename("out/mandel.tiff"), Some(Format::Tiff));
+        assert_eq!(Format::from_filename("mandel.npy"), Some(Format::Npy));
+        assert_eq!(Format::from_filename("mandel.gif"), None);
+        assert_eq!(Format::from_filename("mandel"), None);
+    }
+
+    #[test]
+    fn npy_header_is_aligned() {
+        let header = npy_header((1000, 750));
+        assert_eq!(header.len() % 64, 0);
+        assert_eq!(&header[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(u16::from_le_bytes([header[8], header[9]]) as usize, header.len() - 10);
+        assert!(String::from_utf8_lossy(&header).contains("'shape': (750, 1000)"));
+        assert_eq!(header.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn writes_rows() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+
+        let bounds = (8, 4);
+        let pixels: Vec<u8> = (0..32).map(|shade| shade * 8).collect();
+        for extension in ["png", "pgm", "ppm"].iter() {
+            let filename = format!("{}/mandel.{}", directory.display(), extension);
+            let mut writer = RowWriter::create(&filename, &params(bounds)).unwrap();
+            writer.write_rows(&pixels[..24]).unwrap();
+            writer.write_rows(&pixels[24..]).unwrap();
+            writer.finish().unwrap();
+
+            let image = image::open(&filename).unwrap().into_luma8();
+            assert_eq!(image.dimensions(), (8, 4), "{}", extension);
+            assert_eq!(image.into_raw(), pixels, "{}", extension);
+        }
+
+        assert!(RowWriter::create(&format!("{}/mandel.jpg", directory.display()), &params(bounds)).is_err());
+    }
+
+    #[test]
+    fn writes_every_format() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+
+        let bounds = (8, 4);
+        let pixels: Vec<u8> = (0..32).map(|shade| shade * 8).collect();
+        for extension in ["png", "jpg", "webp", "tiff", "pgm", "ppm"].iter() {
+            let filename = format!("{}/mandel.{}", directory.display(), extension);
+            write_image(&filename, &pixels, &params(bounds)).unwrap();
+            assert_eq!(image::image_dimensions(&filename).unwrap(), (8, 4), "{}", extension);
+        }
+
+        let counts: Vec<u32> = (0..32).collect();
+        let filename = format!("{}/mandel.u32", directory.display());
+        write_iterations(&filename, &counts, bounds).unwrap();
+        assert_eq!(std::fs::read(&filename).unwrap().len(), 32 * 4);
+
+        let filename = format!("{}/mandel.npy", directory.display());
+        write_iterations(&filename, &counts, bounds).unwrap();
+        assert_eq!(std::fs::read(&filename).unwrap().len(), 128 + 32 * 4);
+
+        assert!(write_image(&filename, &pixels, &params(bounds)).is_err());
+
+        let rgb: Vec<u8> = (0..96).collect();
+        let filename = format!("{}/nebula.png", directory.display());
+        write_channels(&filename, &rgb, bounds, 3).unwrap();
+        assert_eq!(image::open(&filename).unwrap().into_rgb8().into_raw(), rgb);
+    }
+}
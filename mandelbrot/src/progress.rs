@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Shared between the threads rendering an image and whoever is watching them, counting the rows rendered so far and
+/// asking the threads to stop early
+#[derive(Debug, Default)]
+pub struct Progress {
+    rows: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress::default()
+    }
+
+    /// The number of rows rendered so far
+    pub fn rows(&self) -> usize {
+        self.rows.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn row_done(&self) {
+        self.rows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Asks the rendering threads to stop once they finish the row they are rendering.  The rows that were not
+    /// rendered are left as they were.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
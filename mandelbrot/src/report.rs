@@ -0,0 +1,83 @@
+use std::io::{Error, IsTerminal, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mandelbrot::Progress;
+
+/// The number of characters in the progress bar
+const BAR_WIDTH: usize = 40;
+
+/// How often the progress bar is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancels the render on the first Ctrl-C, so that the rows rendered so far are still written, and exits straight away
+/// on the second
+pub(crate) fn cancel_on_interrupt(progress: &Arc<Progress>) -> Result<(), Error> {
+    let progress = Arc::clone(progress);
+    ctrlc::set_handler(move || {
+        if progress.is_cancelled() {
+            std::process::exit(130);
+        }
+        progress.cancel();
+    }).map_err(Error::other)
+}
+
+/// Runs the render while drawing a progress bar with the estimated time left on the standard error, when this is a
+/// terminal
+pub(crate) fn show_progress<T>(progress: &Progress, rows: usize, render: impl FnOnce() -> T) -> T {
+    if !std::io::stderr().is_terminal() {
+        return render();
+    }
+
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let start = Instant::now();
+            while !done.load(Ordering::Relaxed) {
+                eprint!("\r{}", progress_bar(progress.rows(), rows, start.elapsed()));
+                let _ = std::io::stderr().flush();
+                thread::sleep(REDRAW_INTERVAL);
+            }
+            eprint!("\r{}\r", " ".repeat(BAR_WIDTH + 30));
+        });
+
+        let result = render();
+        done.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+/// Such as `[##########..............................]  25%  ETA 0:12`, where the time left assumes that the remaining
+/// rows take as long as those rendered so far
+fn progress_bar(rendered: usize, rows: usize, elapsed: Duration) -> String {
+    let rendered = rendered.min(rows);
+    let filled = (rendered * BAR_WIDTH).checked_div(rows).unwrap_or(BAR_WIDTH);
+    let percent = (rendered * 100).checked_div(rows).unwrap_or(100);
+    let eta = if rendered == 0 {
+        "--:--".to_owned()
+    } else {
+        let left = (elapsed.as_secs_f64() * (rows - rendered) as f64 / rendered as f64).round() as u64;
+        format!("{}:{:02}", left / 60, left % 60)
+    };
+
+    format!("[{}{}] {:3}%  ETA {}", "#".repeat(filled), ".".repeat(BAR_WIDTH - filled), percent, eta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_progress_bars() {
+        assert_eq!(progress_bar(0, 100, Duration::from_secs(1)),
+                   format!("[{}]   0%  ETA --:--", ".".repeat(40)));
+        assert_eq!(progress_bar(25, 100, Duration::from_secs(4)),
+                   format!("[{}{}]  25%  ETA 0:12", "#".repeat(10), ".".repeat(30)));
+        assert_eq!(progress_bar(10, 1000, Duration::from_secs(10)),
+                   format!("[{}]   1%  ETA 16:30", ".".repeat(40)));
+        assert_eq!(progress_bar(100, 100, Duration::from_secs(4)),
+                   format!("[{}] 100%  ETA 0:00", "#".repeat(40)));
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::Path;
+
+use crate::RenderParams;
+
+/// The keyword of the PNG text chunk holding the scene an image was rendered from, as JSON
+pub const PNG_KEYWORD: &str = "mandelbrot-scene";
+
+/// Reads the scene from a TOML or JSON file, or from the text metadata of a PNG image rendered before
+pub fn load(filename: &str) -> Result<RenderParams, Error> {
+    let params: RenderParams = match extension(filename).as_str() {
+        "toml" => toml::from_str(&fs::read_to_string(filename)?).map_err(invalid_data)?,
+        "json" => serde_json::from_str(&fs::read_to_string(filename)?).map_err(invalid_data)?,
+        "png" => from_png(filename)?,
+        _ => return Err(unsupported_format(filename)),
+    };
+
+    params.validate().map_err(invalid_data)?;
+    Ok(params)
+}
+
+/// Writes the scene to a TOML or JSON file
+pub fn save(filename: &str, params: &RenderParams) -> Result<(), Error> {
+    let text = match extension(filename).as_str() {
+        "toml" => toml::to_string(params).map_err(Error::other)?,
+        "json" => serde_json::to_string_pretty(params)?,
+        _ => return Err(unsupported_format(filename)),
+    };
+
+    fs::write(filename, text)
+}
+
+pub(crate) fn to_json(params: &RenderParams) -> String {
+    serde_json::to_string(params).expect("the parameters can always be written as JSON")
+}
+
+fn from_png(filename: &str) -> Result<RenderParams, Error> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(filename)?));
+    let reader = decoder.read_info().map_err(invalid_data)?;
+    let chunk = reader.info().uncompressed_latin1_text.iter()
+        .find(|chunk| chunk.keyword == PNG_KEYWORD)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("'{}' has no embedded scene", filename)))?;
+
+    serde_json::from_str(&chunk.text).map_err(invalid_data)
+}
+
+fn extension(filename: &str) -> String {
+    Path::new(filename).extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn unsupported_format(filename: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, format!("scenes are saved as toml or json files: '{}'", filename))
+}
+
+fn invalid_data<E: ToString>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Complex;
+    use crate::output::write_image;
+
+    use super::*;
+
+    fn params() -> RenderParams {
+        RenderParams {
+            samples: 2,
+            limit: 1000,
+            ..RenderParams::new((40, 30), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 })
+        }
+    }
+
+    fn temporary_file(directory: &tempfile::TempDir, name: &str) -> String {
+        directory.path().join(name).display().to_string()
+    }
+
+    #[test]
+    fn saves_and_loads_scenes() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in ["scene.toml", "scene.json"].iter() {
+            let filename = temporary_file(&directory, name);
+            save(&filename, &params()).unwrap();
+            assert_eq!(load(&filename).unwrap(), params());
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_modes() {
+        let params = RenderParams { mode: crate::Mode::Trap(crate::Trap::Line(Complex { re: 0.0, im: 0.5 })), ..params() };
+        let directory = tempfile::tempdir().unwrap();
+        for name in ["mode.toml", "mode.json"].iter() {
+            let filename = temporary_file(&directory, name);
+            save(&filename, &params).unwrap();
+            assert_eq!(load(&filename).unwrap(), params);
+        }
+    }
+
+    #[test]
+    fn reads_toml_scenes() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = temporary_file(&directory, "written.toml");
+        fs::write(&filename, "bounds = [400, 300]\nupper_left = [-2.0, 1.5]\nlower_right = [2.0, -1.5]\n").unwrap();
+
+        let params = load(&filename).unwrap();
+        assert_eq!(params, RenderParams::new((400, 300), Complex { re: -2.0, im: 1.5 }, Complex { re: 2.0, im: -1.5 }));
+
+        fs::write(&filename, "bounds = [0, 300]\nupper_left = [-2.0, 1.5]\nlower_right = [2.0, -1.5]\n").unwrap();
+        assert_eq!(load(&filename).unwrap_err().to_string(), "the image must be at least 1x1 pixels, but got 0x300");
+
+        fs::write(&filename, "bounds = [400, 300]\nupper_left = [-2.0, 1.5]\nlower_rigth = [2.0, -1.5]\n").unwrap();
+        assert!(load(&filename).is_err());
+    }
+
+    #[test]
+    fn loads_scenes_embedded_in_images() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = temporary_file(&directory, "image.png");
+        write_image(&filename, &vec![0; 40 * 30], &params()).unwrap();
+        assert_eq!(load(&filename).unwrap(), params());
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert!(save("scene.yaml", &params()).is_err());
+        assert!(load("scene.yaml").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::{App, HttpResponse, HttpServer, web};
+use mandelbrot::output::encode_png;
+use mandelbrot::{Complex, RenderParams, render};
+use tokio::sync::Semaphore;
+
+/// Width and height of every tile in pixels
+const TILE_SIZE: usize = 256;
+
+/// The deepest zoom level served, beyond which `f64` can no longer tell neighbouring pixels apart
+pub(crate) const MAX_LEVEL: u32 = 40;
+
+/// The centre of the single tile at level 0, which is 4 units wide and covers the whole set
+const ORIGIN: Complex<f64> = Complex { re: -0.75, im: 0.0 };
+
+/// The iterations added to the limit at every zoom level, as the points near the boundary take longer to escape the
+/// deeper the zoom, which would otherwise leave the deep tiles black
+const ITERATIONS_PER_LEVEL: usize = 100;
+
+/// Used to give every temporary tile file a unique name
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TileServer {
+    pub(crate) address: String,
+    pub(crate) cache: Option<PathBuf>,
+    pub(crate) workers: usize,
+    pub(crate) samples: usize,
+    /// The iteration limit at level 0, which grows with every level
+    pub(crate) limit: usize,
+}
+
+impl TileServer {
+    /// Serves the tiles until the server is stopped
+    pub(crate) fn run(self) -> Result<(), Error> {
+        let address = self.address.clone();
+        let renders = web::Data::new(Semaphore::new(self.workers));
+        let server = web::Data::new(self);
+
+        let serve = HttpServer::new(move || {
+            App::new()
+                .app_data(server.clone())
+                .app_data(renders.clone())
+                .route("/", web::get().to(get_index))
+                .route("/tiles/{z}/{x}/{tile}", web::get().to(get_tile))
+        });
+
+        println!("Serving on http://{}", address);
+        actix_web::rt::System::new().block_on(serve.bind(&address)?.run())
+    }
+
+    /// The tile as a PNG image, read from the cache when it was rendered before
+    fn tile(&self, tile: Tile) -> Result<Vec<u8>, Error> {
+        if let Some(png) = self.cached(tile) {
+            return Ok(png);
+        }
+
+        let cached = self.cache_path(tile);
+        let params = RenderParams { samples: self.samples, limit: self.limit(tile.z), ..tile.params() };
+        let mut pixels = vec![0; TILE_SIZE * TILE_SIZE];
+        render(&mut pixels, &params);
+        let png = encode_png(&pixels, &params)?;
+
+        if let Some(path) = &cached {
+            // Written to a temporary file first, so that other workers never read a partially written tile
+            fs::create_dir_all(path.parent().unwrap())?;
+            let temporary = path.with_extension(format!("{}.tmp", TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)));
+            fs::write(&temporary, &png)?;
+            fs::rename(&temporary, path)?;
+        }
+
+        Ok(png)
+    }
+
+    /// The tile as it was rendered before, if it is in the cache
+    fn cached(&self, tile: Tile) -> Option<Vec<u8>> {
+        self.cache_path(tile).and_then(|path| fs::read(path).ok())
+    }
+
+    /// Where the tile is kept in the cache, apart from the tiles rendered with other samples or iterations
+    fn cache_path(&self, tile: Tile) -> Option<PathBuf> {
+        self.cache.as_ref().map(|cache| {
+            cache.join(format!("aa{}-iterations{}", self.samples, self.limit))
+                .join(tile.z.to_string())
+                .join(tile.x.to_string())
+                .join(format!("{}.png", tile.y))
+        })
+    }
+
+    fn limit(&self, level: u32) -> usize {
+        self.limit.saturating_add(level as usize * ITERATIONS_PER_LEVEL)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tile {
+    z: u32,
+    x: u64,
+    y: u64,
+}
+
+impl Tile {
+    /// Parses the tile coordinates from a request path such as `/tiles/3/4/5.png`
+    fn parse(z: &str, x: &str, file: &str) -> Result<Tile, String> {
+        let y = file.strip_suffix(".png").ok_or_else(|| format!("unsupported tile format '{}'", file))?;
+        let z: u32 = z.parse().map_err(|_| format!("invalid zoom level '{}'", z))?;
+        let x: u64 = x.parse().map_err(|_| format!("invalid tile column '{}'", x))?;
+        let y: u64 = y.parse().map_err(|_| format!("invalid tile row '{}'", y))?;
+
+        if z > MAX_LEVEL {
+            return Err(format!("zoom level {} is deeper than the maximum of {}", z, MAX_LEVEL));
+        }
+        if x >= 1 << z || y >= 1 << z {
+            return Err(format!("tile {}/{} is outside level {}", x, y, z));
+        }
+
+        Ok(Tile { z, x, y })
+    }
+
+    fn params(&self) -> RenderParams {
+        let tiles = (1u64 << self.z) as f64;
+        let width = 4.0 / tiles;
+        let center = Complex {
+            re: ORIGIN.re - 2.0 + (self.x as f64 + 0.5) * width,
+            im: ORIGIN.im + 2.0 - (self.y as f64 + 0.5) * width,
+        };
+        RenderParams::around(center, tiles, (TILE_SIZE, TILE_SIZE))
+    }
+}
+
+/// Renders the tile on the blocking thread pool, where the semaphore limits the number of tiles rendered at the same
+/// time to the number of workers.  Cached tiles are served without waiting for a worker.
+async fn get_tile(server: web::Data<TileServer>,
+                  renders: web::Data<Semaphore>,
+                  path: web::Path<(String, String, String)>,
+) -> HttpResponse {
+    let tile = match Tile::parse(&path.0, &path.1, &path.2) {
+        Ok(tile) => tile,
+        Err(e) => return HttpResponse::NotFound().content_type("text/plain").body(e),
+    };
+
+    let cached = {
+        let server = server.clone();
+        web::block(move || server.cached(tile)).await.ok().flatten()
+    };
+    if let Some(png) = cached {
+        return HttpResponse::Ok().content_type("image/png").body(png);
+    }
+
+    let _permit = renders.acquire().await.expect("the semaphore is never closed");
+    match web::block(move || server.tile(tile)).await.map_err(Error::other).and_then(|png| png) {
+        Ok(png) => HttpResponse::Ok()
+            .content_type("image/png")
+            .body(png),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("Failed to render tile: {}", e)),
+    }
+}
+
+async fn get_index() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(format!(
+            r###"
+                <html><head>
+                <title>Mandelbrot</title>
+                <link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css" />
+                <script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+                <style>html, body, #map {{ height: 100%; margin: 0; }}</style>
+                </head><body>
+                <div id="map"></div>
+                <script>
+                var map = L.map('map', {{ crs: L.CRS.Simple, maxZoom: {max_zoom} }}).setView([-128, 128], 1);
+                L.tileLayer('/tiles/{{z}}/{{x}}/{{y}}.png', {{
+                    tileSize: {tile_size}, noWrap: true, maxZoom: {max_zoom},
+                    bounds: [[-{tile_size}, 0], [0, {tile_size}]]
+                }}).addTo(map);
+                </script>
+                </body></html>
+            "###,
+            max_zoom = MAX_LEVEL,
+            tile_size = TILE_SIZE
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tiles() {
+        assert_eq!(Tile::parse("3", "4", "5.png"), Ok(Tile { z: 3, x: 4, y: 5 }));
+        assert_eq!(Tile::parse("3", "8", "5.png"), Err("tile 8/5 is outside level 3".to_owned()));
+        assert_eq!(Tile::parse("41", "0", "0.png"), Err("zoom level 41 is deeper than the maximum of 40".to_owned()));
+        assert_eq!(Tile::parse("3", "4", "5.jpg"), Err("unsupported tile format '5.jpg'".to_owned()));
+        assert_eq!(Tile::parse("a", "4", "5.png"), Err("invalid zoom level 'a'".to_owned()));
+    }
+
+    #[test]
+    fn deeper_tiles_iterate_more() {
+        let server = TileServer { address: String::new(), cache: None, workers: 1, samples: 1, limit: 255 };
+        assert_eq!(server.limit(0), 255);
+        assert_eq!(server.limit(MAX_LEVEL), 255 + 40 * ITERATIONS_PER_LEVEL);
+    }
+
+    #[test]
+    fn tile_regions() {
+        assert_eq!(Tile { z: 0, x: 0, y: 0 }.params(),
+                   RenderParams::new((256, 256), Complex { re: -2.75, im: 2.0 }, Complex { re: 1.25, im: -2.0 }));
+        assert_eq!(Tile { z: 1, x: 1, y: 0 }.params(),
+                   RenderParams::new((256, 256), Complex { re: -0.75, im: 2.0 }, Complex { re: 1.25, im: 0.0 }));
+        assert_eq!(Tile { z: 2, x: 0, y: 3 }.params(),
+                   RenderParams::new((256, 256), Complex { re: -2.75, im: -1.0 }, Complex { re: -1.75, im: -2.0 }));
+    }
+
+    #[test]
+    fn caches_tiles() {
+        let temporary = tempfile::tempdir().unwrap();
+        let cache = temporary.path().join("tiles");
+        let server = TileServer {
+            address: "127.0.0.1:0".to_owned(),
+            cache: Some(cache.clone()),
+            workers: 1,
+            samples: 1,
+            limit: 255,
+        };
+
+        let tile = Tile { z: 2, x: 1, y: 1 };
+        let png = server.tile(tile).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        let path = cache.join("aa1-iterations255").join("2").join("1").join("1.png");
+        assert_eq!(fs::read(&path).unwrap(), png);
+
+        // Cached tiles are served as they are, without rendering them again
+        fs::write(&path, b"cached").unwrap();
+        assert_eq!(server.tile(tile).unwrap(), b"cached");
+
+        // The tiles rendered with other samples or iterations are kept apart
+        let smooth = TileServer { samples: 2, ..server.clone() };
+        assert_eq!(&smooth.tile(tile).unwrap()[1..4], b"PNG");
+        let deeper = TileServer { limit: 1000, ..server.clone() };
+        assert_eq!(&deeper.tile(tile).unwrap()[1..4], b"PNG");
+        assert!(cache.join("aa2-iterations255").join("2").join("1").join("1.png").is_file());
+    }
+}
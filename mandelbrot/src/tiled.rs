@@ -0,0 +1,140 @@
+use std::io::Error;
+
+use mandelbrot::output::RowWriter;
+use mandelbrot::equalize::{self, Equalizer};
+use mandelbrot::{Progress, concurrent_render_rows, render_rows};
+
+use crate::cli::Render;
+
+/// Renders the image in horizontal tiles of the given number of rows, streaming each tile to the output file as soon
+/// as it is rendered.  Only one tile is held in memory at a time, no matter how large the image is.  Once cancelled,
+/// the remaining rows are written black so that the image is still complete.  Equalised images take a first pass over
+/// the whole image to collect the histogram, which needs no pixels to be held either, and nothing is written when they
+/// are cancelled during that pass.  Returns whether the image was written.
+pub(crate) fn render_tiled(options: &Render, tile_rows: usize, progress: &Progress) -> Result<bool, Error> {
+    let bounds = options.params.bounds;
+    let equalizer = match (options.params.equalize, options.concurrent) {
+        (false, _) => None,
+        (true, true) => Some(Equalizer::new(&equalize::concurrent_histogram(&options.params, progress))),
+        (true, false) => Some(Equalizer::new(&equalize::histogram(&options.params, progress))),
+    };
+    if equalizer.is_some() && progress.is_cancelled() {
+        return Ok(false);
+    }
+    let mut writer = RowWriter::create(&options.output, &options.params)?;
+    let mut pixels = vec![0; bounds.0 * tile_rows.min(bounds.1)];
+
+    for top in (0..bounds.1).step_by(tile_rows) {
+        let height = tile_rows.min(bounds.1 - top);
+        let (params, rows) = (&options.params, top..top + height);
+        let tile = &mut pixels[..bounds.0 * height];
+        tile.fill(0);
+
+        match (&equalizer, options.concurrent) {
+            (Some(equalizer), true) => equalize::concurrent_render_rows(tile, params, rows, equalizer, progress),
+            (Some(equalizer), false) => equalize::render_rows(tile, params, rows, equalizer, progress),
+            (None, true) => concurrent_render_rows(tile, params, rows, progress),
+            (None, false) => render_rows(tile, params, rows, progress),
+        }
+
+        writer.write_rows(tile)?;
+    }
+
+    writer.finish()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use mandelbrot::output::Format;
+    use mandelbrot::{Complex, RenderParams, render};
+
+    use super::*;
+
+    #[test]
+    fn tiled_image_matches_whole_image() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = directory.path().join("tiled.png");
+        let options = Render {
+            output: filename.display().to_string(),
+            format: Format::Png,
+            params: RenderParams::new((120, 90), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }),
+            concurrent: true,
+            tile_rows: Some(16),
+            save_scene: None,
+        };
+
+        let progress = Progress::new();
+        render_tiled(&options, 16, &progress).unwrap();
+        assert_eq!(progress.rows(), 90);
+
+        let mut expected = vec![0; 120 * 90];
+        render(&mut expected, &options.params);
+
+        let image = image::open(&filename).unwrap().into_luma8();
+        assert_eq!(image.dimensions(), (120, 90));
+        assert!(image.into_raw() == expected);
+
+        // Every tile is rendered at the points of the whole image, whatever its size
+        let options = Render { concurrent: false, ..options };
+        for tile_rows in [7, 13, 90] {
+            render_tiled(&options, tile_rows, &Progress::new()).unwrap();
+            assert!(image::open(&filename).unwrap().into_luma8().into_raw() == expected, "{} rows", tile_rows);
+        }
+    }
+
+    #[test]
+    fn tiled_image_is_equalized_over_the_whole_image() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = directory.path().join("equalized.pgm");
+        let options = Render {
+            output: filename.display().to_string(),
+            format: Format::Pgm,
+            params: RenderParams {
+                equalize: true,
+                ..RenderParams::new((120, 90), Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 })
+            },
+            concurrent: false,
+            tile_rows: Some(16),
+            save_scene: None,
+        };
+
+        let progress = Progress::new();
+        render_tiled(&options, 16, &progress).unwrap();
+        assert_eq!(progress.rows(), 2 * 90);
+
+        let mut expected = vec![0; 120 * 90];
+        let equalizer = Equalizer::new(&equalize::histogram(&options.params, &Progress::new()));
+        equalize::render(&mut expected, &options.params, &equalizer, &Progress::new());
+
+        assert!(image::open(&filename).unwrap().into_luma8().into_raw() == expected);
+    }
+
+    #[test]
+    fn cancelled_tiled_image_is_black() {
+        let directory = tempfile::tempdir().unwrap();
+        let filename = directory.path().join("cancelled.pgm");
+        let options = Render {
+            output: filename.display().to_string(),
+            format: Format::Pgm,
+            params: RenderParams::new((120, 90), Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }),
+            concurrent: false,
+            tile_rows: Some(16),
+            save_scene: None,
+        };
+
+        let progress = Progress::new();
+        progress.cancel();
+        assert!(render_tiled(&options, 16, &progress).unwrap());
+
+        let image = image::open(&filename).unwrap().into_luma8();
+        assert_eq!(image.dimensions(), (120, 90));
+        assert!(image.into_raw().iter().all(|&pixel| pixel == 0));
+
+        // Nothing is written when an equalised image is cancelled while collecting the histogram
+        std::fs::remove_file(&filename).unwrap();
+        let options = Render { params: RenderParams { equalize: true, ..options.params }, ..options };
+        assert!(!render_tiled(&options, 16, &progress).unwrap());
+        assert!(!filename.exists());
+    }
+}
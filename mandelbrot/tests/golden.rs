@@ -0,0 +1,129 @@
+//! Compares rendered images against the reference images checked in under `tests/golden`, so that changes to the
+//! renderer that alter its output are noticed.  After a deliberate change, the references are rendered again with
+//!
+//! ```shell
+//! $ MANDELBROT_UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+
+use std::path::PathBuf;
+
+use image::ExtendedColorType;
+use mandelbrot::buddhabrot::Buddhabrot;
+use mandelbrot::{Complex, Mode, RenderParams, Trap, concurrent_render, render, render_image};
+
+/// The largest difference in shade a pixel may have from the reference without being counted as different.  This only
+/// allows for the floating point functions rounding differently on other platforms than the one the references were
+/// rendered on, as the same platform always renders the same image.
+const MAX_DIFFERENCE: u8 = 2;
+
+/// The share of pixels that may differ from the reference by more than `MAX_DIFFERENCE`, for the same reason
+const MAX_DIFFERING: f64 = 0.002;
+
+const BOUNDS: (usize, usize) = (160, 120);
+
+fn whole_set() -> RenderParams {
+    RenderParams::around(Complex { re: -0.75, im: 0.0 }, 1.0, BOUNDS)
+}
+
+fn seahorse_valley() -> RenderParams {
+    RenderParams {
+        samples: 3,
+        limit: 1000,
+        ..RenderParams::around(Complex { re: -0.743643, im: 0.131825 }, 200.0, BOUNDS)
+    }
+}
+
+fn reference(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name))
+}
+
+/// Checks the pixels against the reference image, or writes them as the reference when updating
+fn assert_matches_reference(name: &str, pixels: &[u8], channels: usize) {
+    let path = reference(name);
+    let colour = if channels == 3 { ExtendedColorType::Rgb8 } else { ExtendedColorType::L8 };
+
+    if std::env::var_os("MANDELBROT_UPDATE_GOLDEN").is_some() {
+        image::save_buffer(&path, pixels, BOUNDS.0 as u32, BOUNDS.1 as u32, colour).unwrap();
+        return;
+    }
+
+    let expected = image::open(&path)
+        .unwrap_or_else(|e| panic!("cannot read the reference image {}: {}", path.display(), e));
+    let expected = if channels == 3 { expected.into_rgb8().into_raw() } else { expected.into_luma8().into_raw() };
+    assert_eq!(expected.len(), pixels.len(), "{} has a different size than the reference", name);
+
+    let differing = pixels.iter().zip(expected.iter())
+        .filter(|(&actual, &expected)| actual.abs_diff(expected) > MAX_DIFFERENCE)
+        .count();
+    if differing as f64 > MAX_DIFFERING * pixels.len() as f64 {
+        let actual = std::env::temp_dir().join(format!("{}.actual.png", name));
+        image::save_buffer(&actual, pixels, BOUNDS.0 as u32, BOUNDS.1 as u32, colour).unwrap();
+        panic!("{} differs from the reference in {} of {} values, the rendered image was written to {}",
+               name, differing, pixels.len(), actual.display());
+    }
+}
+
+/// Renders on a single thread, which has to match the reference, and concurrently, which has to give exactly the same
+/// pixels as the single thread, however many bands the image is split into
+fn assert_renders_reference(name: &str, params: &RenderParams) {
+    let mut pixels = vec![0; BOUNDS.0 * BOUNDS.1];
+    render(&mut pixels, params);
+    assert_matches_reference(name, &pixels, 1);
+
+    let mut concurrent = vec![0; BOUNDS.0 * BOUNDS.1];
+    concurrent_render(&mut concurrent, params);
+    assert!(concurrent == pixels, "{} renders differently concurrently", name);
+}
+
+#[test]
+fn escape_time() {
+    assert_renders_reference("escape-time", &whole_set());
+}
+
+#[test]
+fn anti_aliased_zoom() {
+    assert_renders_reference("seahorse-valley", &seahorse_valley());
+}
+
+#[test]
+fn distance() {
+    assert_renders_reference("distance", &RenderParams { mode: Mode::Distance, ..whole_set() });
+}
+
+#[test]
+fn relief() {
+    assert_renders_reference("relief", &RenderParams { mode: Mode::Relief, ..seahorse_valley() });
+}
+
+#[test]
+fn interior() {
+    assert_renders_reference("interior", &RenderParams { mode: Mode::Interior, ..whole_set() });
+}
+
+#[test]
+fn cross_trap() {
+    let trap = Trap::Cross(Complex { re: -0.5, im: 0.0 });
+    assert_renders_reference("cross-trap", &RenderParams { mode: Mode::Trap(trap), ..whole_set() });
+}
+
+#[test]
+fn equalized() {
+    let image = render_image(&RenderParams { equalize: true, ..seahorse_valley() });
+    assert_matches_reference("equalized", &image.into_raw(), 1);
+}
+
+/// The points are random, but drawn from ChaCha8 with a fixed seed, whose numbers are the same on every platform and
+/// in every version of the crate, so the image can be checked against a reference like the others
+#[test]
+fn nebulabrot() {
+    let params = whole_set();
+    let nebulabrot = Buddhabrot {
+        bounds: BOUNDS,
+        upper_left: params.upper_left,
+        lower_right: params.lower_right,
+        limits: vec![500, 100, 20],
+        samples: 100_000,
+        seed: 0,
+    };
+    assert_matches_reference("nebulabrot", &nebulabrot.render(), 3);
+}
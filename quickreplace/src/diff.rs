@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+use similar::{ChangeTag, DiffOp, TextDiff};
+use text_colorizer::{ColoredString, Colorize};
+
+/// The number of unchanged lines shown around every change
+const CONTEXT_LINES: usize = 3;
+
+/// Formats the changes between the texts as a unified diff, like `diff -u` does.  When colouring, the removed lines are
+/// red and the added ones green, with the text that actually changed within them underlined.
+pub(crate) fn unified_diff(original: &str, replaced: &str, old_name: &str, new_name: &str, colour: bool) -> String {
+    let diff = TextDiff::from_lines(original, replaced);
+    let mut output = String::new();
+
+    for (index, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if index == 0 {
+            writeln!(output, "{}", paint(format!("--- {}", old_name), colour, |s| s.bold())).unwrap();
+            writeln!(output, "{}", paint(format!("+++ {}", new_name), colour, |s| s.bold())).unwrap();
+        }
+        writeln!(output, "{}", paint(hunk_header(group), colour, |s| s.cyan())).unwrap();
+
+        for op in group {
+            for change in diff.iter_inline_changes(op) {
+                let (sign, style): (&str, fn(ColoredString) -> ColoredString) = match change.tag() {
+                    ChangeTag::Equal => (" ", |s| s),
+                    ChangeTag::Delete => ("-", |s| s.red()),
+                    ChangeTag::Insert => ("+", |s| s.green()),
+                };
+
+                output.push_str(&paint(sign.to_owned(), colour, style).to_string());
+                for (emphasised, value) in change.iter_strings_lossy() {
+                    // The line break stays outside of the colouring, so that it does not carry over to the next line
+                    let text = value.trim_end_matches('\n');
+                    let painted = paint(text.to_owned(), colour, style);
+                    if emphasised && colour {
+                        output.push_str(&painted.underline().to_string());
+                    } else {
+                        output.push_str(&painted.to_string());
+                    }
+                    output.push_str(&value[text.len()..]);
+                }
+                if change.missing_newline() {
+                    output.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+    output
+}
+
+/// The `@@ -1,4 +1,4 @@` line giving where the hunk starts and how many lines it covers in either text
+fn hunk_header(group: &[DiffOp]) -> String {
+    let (first, last) = (&group[0], &group[group.len() - 1]);
+    let old = first.old_range().start..last.old_range().end;
+    let new = first.new_range().start..last.new_range().end;
+    format!("@@ -{} +{} @@", range(old), range(new))
+}
+
+/// Lines are counted from 1, except for empty ranges which give the line before them
+fn range(lines: std::ops::Range<usize>) -> String {
+    match lines.len() {
+        0 => format!("{},0", lines.start),
+        1 => format!("{}", lines.start + 1),
+        length => format!("{},{}", lines.start + 1, length),
+    }
+}
+
+fn paint(text: String, colour: bool, style: impl Fn(ColoredString) -> ColoredString) -> ColoredString {
+    if colour {
+        style(text.normal())
+    } else {
+        text.normal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_unified_diffs() {
+        let original: String = (1..=12).map(|line| format!("line {}\n", line)).collect();
+        let replaced = original.replace("line 2\n", "line two\n").replace("line 11", "line eleven");
+
+        assert_eq!(unified_diff(&original, &replaced, "a.txt", "b.txt", false), "\
+--- a.txt
++++ b.txt
+@@ -1,5 +1,5 @@
+ line 1
+-line 2
++line two
+ line 3
+ line 4
+ line 5
+@@ -8,5 +8,5 @@
+ line 8
+ line 9
+ line 10
+-line 11
++line eleven
+ line 12
+");
+    }
+
+    #[test]
+    fn marks_missing_newlines() {
+        assert_eq!(unified_diff("old", "new", "a", "a", false),
+                   "--- a\n+++ a\n@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n\\ No newline at end of file\n");
+        assert_eq!(unified_diff("same\n", "same\n", "a", "a", false), "");
+    }
+
+    #[test]
+    fn colours_removed_and_added_lines() {
+        let diff = unified_diff("keep old here\n", "keep new here\n", "a", "a", true);
+        assert!(diff.contains(&"old".red().underline().to_string()), "{:?}", diff);
+        assert!(diff.contains(&"new".green().underline().to_string()), "{:?}", diff);
+        assert!(diff.ends_with(&format!("{}\n", " here".green())), "{:?}", diff);
+    }
+}
@@ -0,0 +1,247 @@
+//! Reading and writing text in the encoding of the file.  The text is replaced as a `String`, so it is decoded first and
+//! encoded back the same way once replaced: in the same encoding, with the byte order mark it had, if any, and with the
+//! same line endings.  Files whose lines all end with CRLF are replaced with LF line endings, so that patterns such as
+//! `$` and `\n` work the same on them, and the line endings are turned back into CRLF when writing.
+
+use std::convert::TryFrom;
+
+use crate::files;
+
+/// The number of bytes looked at to tell UTF-16 without a byte order mark from other text
+const DETECTION_LENGTH: usize = 8000;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, where every byte is the character with the same code.  As any bytes can be read this way, it is
+    /// never detected and only used when asked for.
+    Latin1,
+}
+
+/// The decoded text and how it was stored
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Decoded {
+    pub(crate) text: String,
+    pub(crate) format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Format {
+    pub(crate) encoding: Encoding,
+    pub(crate) bom: bool,
+    pub(crate) crlf: bool,
+}
+
+impl Encoding {
+    pub(crate) fn parse(name: &str) -> Result<Encoding, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            // The byte order mark decides, when there is one
+            "utf-16" | "utf16" | "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!("unknown encoding '{}', expected utf-8, utf-16, utf-16le, utf-16be or latin-1", name)),
+        }
+    }
+
+    pub(crate) fn is_utf16(self) -> bool {
+        self == Encoding::Utf16Le || self == Encoding::Utf16Be
+    }
+}
+
+/// Tells the encoding from the byte order mark, or else from the zero bytes UTF-16 has in every other byte of mostly
+/// ASCII text.  Anything else is taken as UTF-8, so that data that is not valid UTF-8 fails to decode and is skipped
+/// as not being text, rather than being rewritten as Latin-1.
+pub(crate) fn detect(data: &[u8]) -> Encoding {
+    if data.starts_with(UTF8_BOM) {
+        return Encoding::Utf8;
+    }
+    if data.starts_with(UTF16LE_BOM) {
+        return Encoding::Utf16Le;
+    }
+    if data.starts_with(UTF16BE_BOM) {
+        return Encoding::Utf16Be;
+    }
+
+    let sample = &data[..data.len().min(DETECTION_LENGTH) & !1];
+    if !sample.is_empty() {
+        let zeros = |parity: usize| sample.iter().skip(parity).step_by(2).filter(|&&byte| byte == 0).count();
+        let (even, odd) = (zeros(0), zeros(1));
+        let pairs = sample.len() / 2;
+        // The odd characters beyond ASCII may still have a zero byte on the other side
+        if odd * 2 > pairs && even * 4 < odd {
+            return Encoding::Utf16Le;
+        }
+        if even * 2 > pairs && odd * 4 < even {
+            return Encoding::Utf16Be;
+        }
+    }
+
+    Encoding::Utf8
+}
+
+/// Whether the file should be skipped as binary.  UTF-16 text is full of zero bytes, so only the other encodings are
+/// checked for them.
+pub(crate) fn is_binary(data: &[u8], encoding: Encoding) -> bool {
+    !encoding.is_utf16() && files::is_binary(data)
+}
+
+pub(crate) fn decode(data: &[u8], encoding: Encoding) -> Result<Decoded, String> {
+    let (encoding, bom, data) = match encoding {
+        Encoding::Utf8 => match data.strip_prefix(UTF8_BOM) {
+            Some(rest) => (encoding, true, rest),
+            None => (encoding, false, data),
+        },
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if let Some(rest) = data.strip_prefix(UTF16LE_BOM) {
+                (Encoding::Utf16Le, true, rest)
+            } else if let Some(rest) = data.strip_prefix(UTF16BE_BOM) {
+                (Encoding::Utf16Be, true, rest)
+            } else {
+                (encoding, false, data)
+            }
+        }
+        Encoding::Latin1 => (encoding, false, data),
+    };
+
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8(data.to_vec()).map_err(|_| "the text is not valid UTF-8".to_owned())?,
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if data.len() % 2 != 0 {
+                return Err("the text is not valid UTF-16, as it has an odd number of bytes".to_owned());
+            }
+            let units = data.chunks_exact(2).map(|pair| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                _ => u16::from_be_bytes([pair[0], pair[1]]),
+            });
+            char::decode_utf16(units).collect::<Result<String, _>>()
+                .map_err(|_| "the text is not valid UTF-16".to_owned())?
+        }
+        Encoding::Latin1 => data.iter().map(|&byte| byte as char).collect(),
+    };
+
+    // Only the files where every line break is CRLF are changed, so that the others are written back as they were
+    let crlf = text.contains("\r\n") && text.matches('\n').count() == text.matches("\r\n").count();
+    let text = if crlf { text.replace("\r\n", "\n") } else { text };
+
+    Ok(Decoded { text, format: Format { encoding, bom, crlf } })
+}
+
+pub(crate) fn encode(text: &str, format: &Format) -> Result<Vec<u8>, String> {
+    let crlf;
+    let text = if format.crlf {
+        crlf = text.replace('\n', "\r\n");
+        &crlf
+    } else {
+        text
+    };
+
+    let mut data = Vec::with_capacity(text.len() + 3);
+    match format.encoding {
+        Encoding::Utf8 => {
+            if format.bom {
+                data.extend_from_slice(UTF8_BOM);
+            }
+            data.extend_from_slice(text.as_bytes());
+        }
+        Encoding::Utf16Le => {
+            if format.bom {
+                data.extend_from_slice(UTF16LE_BOM);
+            }
+            data.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        }
+        Encoding::Utf16Be => {
+            if format.bom {
+                data.extend_from_slice(UTF16BE_BOM);
+            }
+            data.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        }
+        Encoding::Latin1 => {
+            for c in text.chars() {
+                let byte = u8::try_from(c as u32)
+                    .map_err(|_| format!("the replaced text holds '{}', which Latin-1 cannot encode", c))?;
+                data.push(byte);
+            }
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Decoded {
+        let decoded = decode(data, detect(data)).unwrap();
+        assert_eq!(encode(&decoded.text, &decoded.format).unwrap(), data);
+        decoded
+    }
+
+    #[test]
+    fn round_trips_utf8_with_and_without_bom() {
+        let decoded = round_trip(b"\xEF\xBB\xBFcaf\xC3\xA9\n");
+        assert_eq!(decoded.text, "café\n");
+        assert_eq!(decoded.format, Format { encoding: Encoding::Utf8, bom: true, crlf: false });
+        assert!(!round_trip("café\n".as_bytes()).format.bom);
+    }
+
+    #[test]
+    fn round_trips_utf16() {
+        let decoded = round_trip(b"\xFF\xFEh\x00i\x00\r\x00\n\x00");
+        assert_eq!(decoded.text, "hi\n");
+        assert_eq!(decoded.format, Format { encoding: Encoding::Utf16Le, bom: true, crlf: true });
+
+        let decoded = round_trip(b"\x00h\x00e\x00l\x00l\x00o\xD8\x3D\xDE\x00");
+        assert_eq!(decoded.text, "hello\u{1F600}");
+        assert_eq!(decoded.format, Format { encoding: Encoding::Utf16Be, bom: false, crlf: false });
+
+        assert!(decode(b"\xFF\xFE\x00\xD8", Encoding::Utf16Le).is_err());
+    }
+
+    #[test]
+    fn round_trips_latin1_only_when_asked_for() {
+        let data = b"caf\xE9\n";
+        assert_eq!(detect(data), Encoding::Utf8);
+        assert_eq!(decode(data, detect(data)), Err("the text is not valid UTF-8".to_owned()));
+
+        let decoded = decode(data, Encoding::Latin1).unwrap();
+        assert_eq!(decoded.text, "café\n");
+        assert_eq!(encode(&decoded.text, &decoded.format).unwrap(), data);
+        assert!(encode("€", &decoded.format).is_err());
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        let decoded = round_trip(b"a\r\nb\r\n");
+        assert_eq!(decoded.text, "a\nb\n");
+        assert!(decoded.format.crlf);
+        assert_eq!(encode("a\nc\nd\n", &decoded.format).unwrap(), b"a\r\nc\r\nd\r\n");
+
+        // Mixed line endings are left as they are
+        let decoded = round_trip(b"a\r\nb\n");
+        assert_eq!(decoded.text, "a\r\nb\n");
+        assert!(!decoded.format.crlf);
+    }
+
+    #[test]
+    fn parses_encoding_names() {
+        assert_eq!(Encoding::parse("UTF-16"), Ok(Encoding::Utf16Le));
+        assert_eq!(Encoding::parse("iso-8859-1"), Ok(Encoding::Latin1));
+        assert!(Encoding::parse("ebcdic").is_err());
+    }
+
+    #[test]
+    fn tells_utf16_from_binary_files() {
+        let utf16 = b"h\x00i\x00";
+        assert_eq!(detect(utf16), Encoding::Utf16Le);
+        assert!(!is_binary(utf16, detect(utf16)));
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+        assert!(is_binary(png, detect(png)));
+    }
+}
@@ -0,0 +1,97 @@
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+
+/// The number of bytes looked at to decide whether a file is binary
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+/// Finds the files under the given paths, honouring `.gitignore` files and skipping hidden files.  When any globs are
+/// given, only the files matching at least one of them are kept.  Like in `.gitignore`, a glob without a `/` matches
+/// the file name at any depth, and any other glob the path from the walked path.
+pub(crate) fn find_files(paths: &[String], globs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let globs = if globs.is_empty() { None } else { Some(glob_set(globs)?) };
+
+    let mut files = Vec::new();
+    for path in paths {
+        for entry in WalkBuilder::new(path).build() {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if !entry.file_type().is_some_and(|file_type| file_type.is_file()) {
+                continue;
+            }
+            if globs.as_ref().is_none_or(|globs| globs.is_match(relative(Path::new(path), entry.path()))) {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, String> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        let pattern = if glob.contains('/') { glob.trim_start_matches('/').to_owned() } else { format!("**/{}", glob) };
+        let glob = GlobBuilder::new(&pattern).literal_separator(true).build()
+            .map_err(|e| format!("Invalid glob '{}': {}", glob, e))?;
+        set.add(glob);
+    }
+    set.build().map_err(|e| e.to_string())
+}
+
+/// The path of the file from the walked path, or its name when the file itself was given
+fn relative<'a>(root: &Path, file: &'a Path) -> &'a Path {
+    match file.strip_prefix(root) {
+        Ok(relative) if relative != Path::new("") => relative,
+        _ => file.file_name().map_or(file, Path::new),
+    }
+}
+
+/// Files holding a zero byte near their start are taken to be binary, as text files never do
+pub(crate) fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(BINARY_CHECK_LENGTH).any(|&byte| byte == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn finds_files_honouring_gitignore_and_globs() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        fs::create_dir_all(directory.join("src")).unwrap();
+        fs::create_dir_all(directory.join("target")).unwrap();
+        // The ignore files only apply inside git repositories
+        fs::create_dir_all(directory.join(".git")).unwrap();
+        fs::write(directory.join(".gitignore"), "target/\nsrc/generated.rs\n").unwrap();
+        fs::write(directory.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(directory.join("src/generated.rs"), "fn generated() {}").unwrap();
+        fs::write(directory.join("src/.hidden.rs"), "fn hidden() {}").unwrap();
+        fs::write(directory.join("src/notes.md"), "notes").unwrap();
+        fs::write(directory.join("target/main.rs"), "fn main() {}").unwrap();
+
+        let paths = vec![directory.display().to_string()];
+        let mut files = find_files(&paths, &[]).unwrap();
+        files.sort();
+        assert_eq!(files, vec![directory.join("src/main.rs"), directory.join("src/notes.md")]);
+
+        // The globs only narrow down the files, never bringing back ignored or hidden ones
+        let files = find_files(&paths, &["*.rs".to_owned()]).unwrap();
+        assert_eq!(files, vec![directory.join("src/main.rs")]);
+
+        // The globs with a path start from the walked path, not from the current directory
+        let files = find_files(&paths, &["src/*.rs".to_owned()]).unwrap();
+        assert_eq!(files, vec![directory.join("src/main.rs")]);
+        assert_eq!(find_files(&paths, &["/*.rs".to_owned()]).unwrap(), Vec::<PathBuf>::new());
+        let file = vec![directory.join("src/main.rs").display().to_string()];
+        assert_eq!(find_files(&file, &["*.rs".to_owned()]).unwrap(), vec![directory.join("src/main.rs")]);
+    }
+
+    #[test]
+    fn detects_binary_files() {
+        assert!(!is_binary(b"plain text\n"));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"));
+    }
+}
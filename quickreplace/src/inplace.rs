@@ -0,0 +1,105 @@
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Used to give every temporary file a unique name, as several files may be written at the same time
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces the contents of the file atomically, by writing them to a temporary file next to it and renaming that over
+/// the file, so that the file is never left half written.  The file keeps its permissions and timestamps.  When a
+/// backup suffix is given, the original file is first copied to a file with the suffix appended to its name.  Symbolic
+/// links are followed, so that the file they point to is replaced rather than the link.
+pub(crate) fn write_in_place(path: &Path, contents: &[u8], backup: Option<&str>) -> Result<(), Error> {
+    let path = &fs::canonicalize(path)?;
+    let metadata = fs::metadata(path)?;
+
+    if let Some(suffix) = backup {
+        let backup = with_suffix(path, suffix);
+        fs::copy(path, &backup)?;
+        File::options().write(true).open(&backup)?.set_times(times(&metadata)?)?;
+    }
+
+    let temporary = with_suffix(path, &format!(".{}.{}.tmp", std::process::id(),
+                                               TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)));
+    let result = write_temporary(&temporary, contents, &metadata).and_then(|_| fs::rename(&temporary, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn write_temporary(temporary: &Path, contents: &[u8], metadata: &Metadata) -> Result<(), Error> {
+    let mut file = File::create(temporary)?;
+    file.write_all(contents)?;
+    file.set_permissions(metadata.permissions())?;
+    file.set_times(times(metadata)?)?;
+    file.sync_all()
+}
+
+fn times(metadata: &Metadata) -> Result<FileTimes, Error> {
+    Ok(FileTimes::new().set_accessed(metadata.accessed()?).set_modified(metadata.modified()?))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_and_timestamps() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let path = directory.join("script.sh");
+        fs::write(&path, "echo old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options().write(true).open(&path).unwrap()
+            .set_times(FileTimes::new().set_modified(modified)).unwrap();
+
+        write_in_place(&path, b"echo new", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo new");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o750);
+        assert_eq!(metadata.modified().unwrap(), modified);
+
+        let backup = directory.join("script.sh.bak");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "echo old");
+        assert_eq!(fs::metadata(&backup).unwrap().modified().unwrap(), modified);
+
+        // Only the file and its backup are left, without any temporary files
+        assert_eq!(fs::read_dir(directory).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_the_file_a_symbolic_link_points_to() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let (target, link) = (directory.join("target.txt"), directory.join("link.txt"));
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_in_place(&link, b"new", Some(".bak")).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        assert_eq!(fs::read_to_string(directory.join("target.txt.bak")).unwrap(), "old");
+    }
+
+    #[test]
+    fn fails_for_missing_files() {
+        assert!(write_in_place(Path::new("missing/file.txt"), b"new", None).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::io::{BufRead, Error, Write};
+
+use text_colorizer::Colorize;
+
+use crate::rules::Rules;
+
+/// The replacements accepted in a file, and whether the user asked to stop looking at any further files
+#[derive(Debug, PartialEq)]
+pub(crate) struct Choices {
+    pub(crate) text: String,
+    /// The number of accepted replacements of every rule
+    pub(crate) counts: Vec<usize>,
+    pub(crate) quit: bool,
+}
+
+/// Shows every match in the text within its lines, as it is and as it would be replaced, and asks whether to replace
+/// it, much like `git add -p` does for hunks.  Only the accepted matches are replaced in the returned text.  The end
+/// of the input is taken as quitting.
+pub(crate) fn choose(rules: &Rules, text: &str, name: &str, input: &mut impl BufRead, output: &mut impl Write,
+                     colour: bool) -> Result<Choices, Error> {
+    let mut replaced = String::with_capacity(text.len());
+    let mut counts = vec![0; rules.len()];
+    let mut last = 0;
+    let mut accept_all = false;
+    let mut quit = false;
+
+    for found in rules.matches(text) {
+        let accept = if accept_all {
+            true
+        } else if quit {
+            false
+        } else {
+            show(text, found.start, found.end, &found.replacement, name, output, colour)?;
+            match ask(input, output)? {
+                Answer::Yes => true,
+                Answer::No => false,
+                Answer::All => {
+                    accept_all = true;
+                    true
+                }
+                Answer::Quit => {
+                    quit = true;
+                    false
+                }
+            }
+        };
+
+        replaced.push_str(&text[last..found.start]);
+        if accept {
+            replaced.push_str(&found.replacement);
+            counts[found.rule] += 1;
+        } else {
+            replaced.push_str(found.original);
+        }
+        last = found.end;
+    }
+    replaced.push_str(&text[last..]);
+
+    Ok(Choices { text: replaced, counts, quit })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+/// Prints the lines holding the match, once with the match removed and once with the replacement added
+fn show(text: &str, start: usize, end: usize, expanded: &str, name: &str, output: &mut impl Write,
+        colour: bool) -> Result<(), Error> {
+    let line_start = text[..start].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = text[end..].find('\n').map_or(text.len(), |index| end + index);
+    let line = text[..start].matches('\n').count() + 1;
+    let (before, found, after) = (&text[line_start..start], &text[start..end], &text[end..line_end]);
+
+    let header = format!("{}:{}", name, line);
+    let (removed, added) = if colour {
+        (format!("{}{}{}", "-".red(), before, found.red().underline()),
+         format!("{}{}{}", "+".green(), before, expanded.green().underline()))
+    } else {
+        (format!("-{}{}", before, found), format!("+{}{}", before, expanded))
+    };
+    writeln!(output, "{}", if colour { header.cyan().to_string() } else { header })?;
+    writeln!(output, "{}{}", removed, after)?;
+    writeln!(output, "{}{}", added, after)
+}
+
+fn ask(input: &mut impl BufRead, output: &mut impl Write) -> Result<Answer, Error> {
+    loop {
+        write!(output, "Replace this match [y,n,a,q,?]? ")?;
+        output.flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        match answer.trim() {
+            "y" => return Ok(Answer::Yes),
+            "n" => return Ok(Answer::No),
+            "a" => return Ok(Answer::All),
+            "q" => return Ok(Answer::Quit),
+            _ => {
+                writeln!(output, "y - replace this match")?;
+                writeln!(output, "n - do not replace this match")?;
+                writeln!(output, "a - replace this match and all the remaining ones in the file")?;
+                writeln!(output, "q - quit; do not replace this match or any of the remaining ones")?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern;
+
+    use super::*;
+
+    fn run(answers: &str, text: &str) -> (Choices, String) {
+        let rules = Rules::new(&[(r"old(\d)".to_owned(), "new$1".to_owned())], &pattern::Options::default()).unwrap();
+        let mut output = Vec::new();
+        let choices = choose(&rules, text, "a.txt", &mut answers.as_bytes(), &mut output, false).unwrap();
+        (choices, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn replaces_the_accepted_matches_only() {
+        let (choices, output) = run("n\ny\n", "old1 old2\nold3\n");
+        assert_eq!(choices, Choices { text: "old1 new2\nold3\n".to_owned(), counts: vec![1], quit: true });
+        assert!(output.starts_with("a.txt:1\n-old1 old2\n+new1 old2\nReplace this match [y,n,a,q,?]? "), "{}", output);
+        assert!(output.contains("a.txt:2\n-old3\n+new3\n"), "{}", output);
+    }
+
+    #[test]
+    fn accepts_all_remaining_matches_in_the_file() {
+        let (choices, output) = run("a\n", "old1 old2\nold3\n");
+        assert_eq!(choices, Choices { text: "new1 new2\nnew3\n".to_owned(), counts: vec![3], quit: false });
+        assert_eq!(output.matches("Replace this match").count(), 1);
+    }
+
+    #[test]
+    fn quits_keeping_the_accepted_matches() {
+        let (choices, _) = run("y\nq\n", "old1 old2 old3");
+        assert_eq!(choices, Choices { text: "new1 old2 old3".to_owned(), counts: vec![1], quit: true });
+    }
+
+    #[test]
+    fn explains_unknown_answers() {
+        let (choices, output) = run("x\ny\n", "old1");
+        assert_eq!(choices.text, "new1");
+        assert!(output.contains("a - replace this match and all the remaining ones in the file"), "{}", output);
+        assert_eq!(output.matches("Replace this match [").count(), 2);
+    }
+}
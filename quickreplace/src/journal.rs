@@ -0,0 +1,348 @@
+//! The journal of the runs that changed files in place, so that they can be undone.  Every run has its own directory in
+//! the journal, named after the time it started so that the runs sort in order, holding a copy of every file as it was
+//! before the run changed it.  The `manifest.jsonl` file of the run lists the copies, one JSON object per line, with a
+//! hash of what the run wrote, so that files changed again since are not overwritten when undoing.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::inplace;
+
+/// The journal directory, relative to the current directory, unless `QUICKREPLACE_JOURNAL` names another
+pub(crate) const DEFAULT_DIRECTORY: &str = ".quickreplace/journal";
+
+const MANIFEST: &str = "manifest.jsonl";
+
+/// Records the files changed by one run.  The run directory is only created once the first file is recorded, so that
+/// runs changing nothing leave nothing behind.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    id: String,
+    directory: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The manifest, once created
+    manifest: Option<File>,
+    entries: Vec<Entry>,
+    /// The number of copies made so far, which names the next one
+    copies: usize,
+}
+
+/// A file changed by a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    /// The name of the copy of the original file, within the run directory
+    original: String,
+    /// The hash of the contents the run wrote, in hexadecimal
+    written: String,
+}
+
+/// The files an undo restored, and those it left alone as they were changed again since the run
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Undone {
+    pub(crate) id: String,
+    pub(crate) restored: Vec<PathBuf>,
+    pub(crate) modified: Vec<PathBuf>,
+}
+
+impl Journal {
+    pub(crate) fn new(root: &Path) -> Journal {
+        let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let id = format!("{:013}-{}", elapsed.as_millis(), std::process::id());
+        Journal { directory: root.join(&id), id, state: Mutex::new(State::default()) }
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether any file was recorded
+    pub(crate) fn is_used(&self) -> bool {
+        !self.state.lock().unwrap().entries.is_empty()
+    }
+
+    /// Keeps a copy of the file as it is, before the given contents are written to it
+    pub(crate) fn record(&self, file: &Path, written: &[u8]) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.manifest.is_none() {
+            fs::create_dir_all(&self.directory)?;
+            state.manifest = Some(OpenOptions::new().create(true).append(true).open(self.directory.join(MANIFEST))?);
+        }
+
+        let entry = Entry {
+            path: fs::canonicalize(file)?,
+            original: format!("{}.orig", state.copies),
+            written: hash(written),
+        };
+        state.copies += 1;
+        fs::copy(file, self.directory.join(&entry.original))?;
+
+        let manifest = state.manifest.as_mut().unwrap();
+        writeln!(manifest, "{}", serde_json::to_string(&entry)?)?;
+        manifest.sync_data()?;
+        state.entries.push(entry);
+        Ok(())
+    }
+
+    /// Drops the record of a file that could not be written after all, removing the run once it records nothing
+    pub(crate) fn discard(&self, file: &Path) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let path = fs::canonicalize(file)?;
+        let index = match state.entries.iter().rposition(|entry| entry.path == path) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let entry = state.entries.remove(index);
+
+        if state.entries.is_empty() {
+            state.manifest = None;
+            return fs::remove_dir_all(&self.directory);
+        }
+
+        // The manifest is only ever appended to, so it is written anew without the entry
+        let mut manifest = File::create(self.directory.join(MANIFEST))?;
+        for entry in &state.entries {
+            writeln!(manifest, "{}", serde_json::to_string(entry)?)?;
+        }
+        manifest.sync_data()?;
+        state.manifest = Some(OpenOptions::new().append(true).open(self.directory.join(MANIFEST))?);
+        fs::remove_file(self.directory.join(&entry.original))
+    }
+}
+
+impl Entry {
+    /// Whether the entry names an absolute path to restore and a copy within the run directory, so that a manifest
+    /// changed by hand cannot reach elsewhere
+    fn is_valid(&self) -> bool {
+        let mut original = Path::new(&self.original).components();
+        self.path.is_absolute()
+            && self.path.components().all(|component| component != Component::ParentDir)
+            && matches!((original.next(), original.next()), (Some(Component::Normal(_)), None))
+    }
+}
+
+/// Restores the files changed by the run, or by the latest run when none is given, unless they were changed again since.
+/// The run is removed from the journal once all its files are restored.
+pub(crate) fn undo(root: &Path, id: Option<&str>) -> Result<Undone, String> {
+    let id = match id {
+        Some(id) if is_run_id(id) => id.to_owned(),
+        Some(id) => return Err(format!("'{}' is not a run, which looks like '1760000000000-42'", id)),
+        None => latest_run(root)?,
+    };
+    let directory = root.join(&id);
+    let manifest = File::open(directory.join(MANIFEST))
+        .map_err(|e| format!("Failed to read run '{}' from the journal: '{}': {:?}", id, root.display(), e))?;
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(manifest).lines() {
+        let line = line.map_err(|e| format!("Failed to read run '{}' from the journal: {:?}", id, e))?;
+        // A line cut short by a run that was stopped while writing it is of no use
+        if let Ok(entry) = serde_json::from_str::<Entry>(&line) {
+            if !entry.is_valid() {
+                return Err(format!("Failed to read run '{}' from the journal: invalid entry '{}'", id, line));
+            }
+            entries.push(entry);
+        }
+    }
+
+    let mut undone = Undone { id: id.clone(), ..Undone::default() };
+    for entry in entries {
+        let original = fs::read(directory.join(&entry.original))
+            .map_err(|e| format!("Failed to read the original of '{}': {:?}", entry.path.display(), e))?;
+        let current = fs::read(&entry.path).ok();
+
+        // The files restored by an earlier undo of the run, when it left others alone, are already as they were
+        if current.as_ref() != Some(&original) {
+            if current.is_none_or(|data| hash(&data) != entry.written) {
+                undone.modified.push(entry.path);
+                continue;
+            }
+            inplace::write_in_place(&entry.path, &original, None)
+                .map_err(|e| format!("Failed to write data to file: '{}': {:?}", entry.path.display(), e))?;
+        }
+        undone.restored.push(entry.path);
+    }
+
+    if undone.modified.is_empty() {
+        fs::remove_dir_all(&directory)
+            .map_err(|e| format!("Failed to remove run '{}' from the journal: {:?}", id, e))?;
+    }
+    Ok(undone)
+}
+
+fn latest_run(root: &Path) -> Result<String, String> {
+    let runs = fs::read_dir(root)
+        .map_err(|e| format!("Failed to read the journal: '{}': {:?}", root.display(), e))?;
+    runs.filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join(MANIFEST).is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|id| run_order(&id).map(|order| (order, id)))
+        .max()
+        .map(|(_, id)| id)
+        .ok_or_else(|| format!("No runs to undo in the journal: '{}'", root.display()))
+}
+
+/// Whether the id is one the journal names its runs with, the milliseconds since the epoch and the process id, so that
+/// it cannot name any other directory
+fn is_run_id(id: &str) -> bool {
+    run_order(id).is_some()
+}
+
+/// The milliseconds and the process id of the run, compared as numbers so that the runs of the same millisecond are
+/// ordered by process id rather than by its digits
+fn run_order(id: &str) -> Option<(u64, u64)> {
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+    let (millis, process) = id.split_once('-')?;
+    if millis.len() != 13 || !digits(millis) || !digits(process) {
+        return None;
+    }
+    Some((millis.parse().ok()?, process.parse().ok()?))
+}
+
+/// The 64 bit FNV-1a hash, which is enough to tell whether a file was changed since it was written
+fn hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_the_latest_run() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let root = directory.join("journal");
+        let (first, second) = (directory.join("first.txt"), directory.join("second.txt"));
+        fs::write(&first, "old").unwrap();
+        fs::write(&second, "old").unwrap();
+
+        let journal = Journal::new(&root);
+        assert!(!journal.is_used());
+        journal.record(&first, b"new").unwrap();
+        fs::write(&first, "new").unwrap();
+        journal.record(&second, b"new").unwrap();
+        fs::write(&second, "new").unwrap();
+        assert!(journal.is_used());
+
+        let undone = undo(&root, None).unwrap();
+        assert_eq!(undone.id, journal.id());
+        assert_eq!(undone.restored, vec![fs::canonicalize(&first).unwrap(), fs::canonicalize(&second).unwrap()]);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "old");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "old");
+
+        // The run is gone once undone
+        assert!(undo(&root, None).unwrap_err().starts_with("No runs to undo"));
+    }
+
+    #[test]
+    fn orders_runs_by_time_and_process_id() {
+        let temporary = tempfile::tempdir().unwrap();
+        let root = temporary.path();
+        for id in ["1760000000000-999", "1760000000000-1000", "1759999999999-5000"] {
+            fs::create_dir_all(root.join(id)).unwrap();
+            fs::write(root.join(id).join(MANIFEST), "").unwrap();
+        }
+
+        assert_eq!(latest_run(root).unwrap(), "1760000000000-1000");
+    }
+
+    #[test]
+    fn leaves_files_changed_since() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let root = directory.join("journal");
+        let (edited, kept) = (directory.join("edited.txt"), directory.join("kept.txt"));
+        fs::write(&edited, "old").unwrap();
+        fs::write(&kept, "old").unwrap();
+
+        let journal = Journal::new(&root);
+        journal.record(&edited, b"new").unwrap();
+        journal.record(&kept, b"new").unwrap();
+        fs::write(&edited, "edited by hand").unwrap();
+        fs::write(&kept, "new").unwrap();
+
+        let undone = undo(&root, Some(journal.id())).unwrap();
+        assert_eq!(undone.restored, vec![fs::canonicalize(&kept).unwrap()]);
+        assert_eq!(undone.modified, vec![fs::canonicalize(&edited).unwrap()]);
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "edited by hand");
+        assert_eq!(fs::read_to_string(&kept).unwrap(), "old");
+        // The run is kept, as it was not completely undone
+        assert!(root.join(journal.id()).is_dir());
+
+        // Once the edited file is restored by hand, the run can be undone again and is then removed
+        fs::write(&edited, "new").unwrap();
+        let undone = undo(&root, Some(journal.id())).unwrap();
+        assert_eq!(undone.restored.len(), 2);
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "old");
+        assert!(!root.join(journal.id()).exists());
+
+        assert!(undo(&root, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn discards_the_files_not_written() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let root = directory.join("journal");
+        let (first, second) = (directory.join("first.txt"), directory.join("second.txt"));
+        fs::write(&first, "old").unwrap();
+        fs::write(&second, "old").unwrap();
+
+        let journal = Journal::new(&root);
+        journal.record(&first, b"new").unwrap();
+        journal.record(&second, b"new").unwrap();
+        journal.discard(&first).unwrap();
+        fs::write(&second, "new").unwrap();
+        assert!(!root.join(journal.id()).join("0.orig").exists());
+
+        let undone = undo(&root, None).unwrap();
+        assert_eq!(undone.restored, vec![fs::canonicalize(&second).unwrap()]);
+
+        // The run is removed once it records nothing
+        let journal = Journal::new(&root);
+        journal.record(&first, b"new").unwrap();
+        journal.discard(&first).unwrap();
+        assert!(!journal.is_used());
+        assert!(!root.join(journal.id()).exists());
+    }
+
+    #[test]
+    fn stays_within_the_journal() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let root = directory.join("journal");
+
+        assert!(is_run_id("1760000000000-42"));
+        for id in ["../x", "/tmp", "1760000000000-42/..", "1760000000000", "176000000000-42", "1760000000000-"] {
+            assert!(!is_run_id(id), "{}", id);
+            assert!(undo(&root, Some(id)).unwrap_err().contains("is not a run"));
+        }
+
+        let run = root.join("1760000000000-42");
+        fs::create_dir_all(&run).unwrap();
+        fs::write(run.join(MANIFEST), r#"{"path":"/tmp/a.txt","original":"../../secret","written":"0"}"#).unwrap();
+        assert!(undo(&root, None).unwrap_err().contains("invalid entry"));
+        fs::write(run.join(MANIFEST), r#"{"path":"a.txt","original":"0.orig","written":"0"}"#).unwrap();
+        assert!(undo(&root, None).unwrap_err().contains("invalid entry"));
+        assert!(run.is_dir());
+    }
+
+    #[test]
+    fn hashes_with_fnv1a() {
+        assert_eq!(hash(b""), "cbf29ce484222325");
+        assert_eq!(hash(b"a"), "af63dc4c8601ec8c");
+    }
+}
@@ -1,66 +1,777 @@
-use std::{env, fs};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
 
-use regex::Regex;
+use rayon::prelude::*;
 use text_colorizer::Colorize;
 
+use encoding::{Decoded, Encoding, Format};
+use journal::Journal;
+use report::{Change, Statistics};
+use rules::{Replaced, Rules};
+
+mod diff;
+mod encoding;
+mod files;
+mod inplace;
+mod interactive;
+mod journal;
+mod pattern;
+mod report;
+mod rules;
+mod stream;
+
 fn main() {
-    let args = parse_args();
-    let data = match fs::read_to_string(&args.filename) {
-        Ok(v) => v,
+    let args: Vec<String> = env::args().skip(1).collect();
+    if is_undo(&args) {
+        undo(args.get(1).map(String::as_str));
+        return;
+    }
+
+    let args = match parse_args(&args) {
+        Ok(args) => args,
         Err(e) => {
-            eprintln!("{} Failed to read from file: '{}': {:?}", "Error".red().bold(), args.filename, e);
+            print_usage();
+            eprintln!("{} - {}", "Error".red().bold(), e);
             std::process::exit(1);
         }
     };
 
-    let replaced_data = match replace(&args.target, &args.replacement, &data) {
+    let rules = match &args.target {
+        Target::Single { target, replacement } => Rules::new(&[(target.clone(), replacement.clone())], &args.pattern)
+            .map_err(|e| format!("Failed to replace text: {}", e)),
+        Target::Rules { path } => Rules::load(Path::new(path), &args.pattern),
+    };
+    let rules = match rules {
         Ok(v) => v,
         Err(e) => {
-            eprintln!("{} Failed to replace text: {:?}", "Error".red().bold(), e);
+            eprintln!("{} {}", "Error".red().bold(), e);
             std::process::exit(1);
         }
     };
 
-    match fs::write(&args.output, &replaced_data) {
-        Ok(_) => {}
+    let journal = Journal::new(&journal_directory());
+    let journal = if args.journal { Some(&journal) } else { None };
+    let write = if args.dry_run {
+        Write::DryRun { colour: io::stdout().is_terminal() }
+    } else if args.interactive {
+        Write::Interactive { backup: args.backup.as_deref(), journal, colour: io::stdout().is_terminal() }
+    } else {
+        Write::Files { backup: args.backup.as_deref(), journal }
+    };
+    let statistics = match &args.input {
+        Input::File { filename, output } if args.lines => replace_lines(&rules, filename, output, args.json),
+        Input::File { filename, output } => replace_file(&rules, filename, output, write, args.json, args.encoding),
+        Input::InPlace { files } => {
+            let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+            replace_files(&rules, &files, write, args.json, args.encoding)
+        }
+        Input::Recursive { paths, globs } => {
+            let files = match files::find_files(paths, globs) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("{} Failed to list files: {}", "Error".red().bold(), e);
+                    std::process::exit(1);
+                }
+            };
+            replace_files(&rules, &files, write, args.json, args.encoding)
+        }
+    };
+
+    if let Target::Rules { .. } = args.target {
+        eprint!("{}", rules.report(&statistics));
+    }
+    if args.json {
+        println!("{}", statistics.json());
+    } else {
+        eprint!("{}", statistics.summary(args.dry_run));
+    }
+    if let Some(journal) = journal.filter(|journal| journal.is_used()) {
+        eprintln!("Undo with: quickreplace undo {}", journal.id());
+    }
+}
+
+/// `undo` is only taken as the command when followed by at most a run, as a replacement needs more arguments
+fn is_undo(args: &[String]) -> bool {
+    args.len() <= 2 && args.first().is_some_and(|arg| arg == "undo")
+}
+
+/// Restores the files changed in place by the run, or by the latest run, that were not changed since
+fn undo(id: Option<&str>) {
+    let undone = match journal::undo(&journal_directory(), id) {
+        Ok(v) => v,
         Err(e) => {
-            eprintln!("{} Failed to write data to file: '{}': {:?}", "Error".red().bold(), args.filename, e);
+            eprintln!("{} {}", "Error".red().bold(), e);
             std::process::exit(1);
         }
     };
+
+    for file in &undone.modified {
+        eprintln!("{} '{}' was changed since run '{}', so it was left as it is", "Warning".yellow().bold(),
+                  file.display(), undone.id);
+    }
+    let total = undone.restored.len() + undone.modified.len();
+    eprintln!("Restored {} of {} {} changed by run '{}'", undone.restored.len(), total,
+              if total == 1 { "file" } else { "files" }, undone.id);
+    if !undone.modified.is_empty() {
+        eprintln!("The run is kept in the journal, so it can be undone again once these files are restored by hand");
+        std::process::exit(1);
+    }
+}
+
+fn journal_directory() -> PathBuf {
+    env::var_os("QUICKREPLACE_JOURNAL").map_or_else(|| PathBuf::from(journal::DEFAULT_DIRECTORY), PathBuf::from)
+}
+
+/// What was printed for a file, the number of replacements every rule made in it, and the replacements themselves when
+/// they are reported as JSON
+#[derive(Debug, Default)]
+struct Outcome {
+    output: String,
+    counts: Vec<usize>,
+    changes: Vec<Change>,
+}
+
+/// What to do with the replaced text
+#[derive(Debug, Clone, Copy)]
+enum Write<'a> {
+    /// Write the replaced text, keeping a copy of the files changed in place when given a backup suffix, and recording
+    /// them in the journal when given one
+    Files { backup: Option<&'a str>, journal: Option<&'a Journal> },
+    /// Only show the changes that would be made, as a unified diff followed by the number of matches
+    DryRun { colour: bool },
+    /// Ask about every match, one file at a time, and write only the accepted replacements
+    Interactive { backup: Option<&'a str>, journal: Option<&'a Journal>, colour: bool },
 }
 
 fn print_usage() {
     eprintln!("{} - change occurrence of one string to another", "quickreplace".green());
-    eprintln!("Usage: quickreplace <target> <replacement> <input-file> <output-file>")
+    eprintln!("Usage: quickreplace [--lines] <target> <replacement> <input-file> <output-file>");
+    eprintln!("       quickreplace --in-place [--backup <suffix>] <target> <replacement> <file>...");
+    eprintln!("       quickreplace --recursive [--glob <pattern>]... [--ext <extension>]... <target> <replacement> <path>...");
+    eprintln!("       quickreplace undo [<run>]");
+    eprintln!();
+    eprintln!("The input and output files may be '-' to read from stdin and write to stdout.  With --rules, the");
+    eprintln!("target and the replacement are left out.  The files changed in place are recorded in the journal, in");
+    eprintln!("'.quickreplace/journal' or else in $QUICKREPLACE_JOURNAL, and undo restores those of the run, or of the");
+    eprintln!("latest run, unless they were changed since.");
+    eprintln!();
+    eprintln!("      --rules <file>       replace the targets of the rules in a TOML or YAML file, in a single pass");
+    eprintln!("  -F, --fixed-strings      take the target and the replacement as plain text, not as a regex");
+    eprintln!("  -w, --word               only match whole words");
+    eprintln!("  -i, --ignore-case        match regardless of case");
+    eprintln!("  -m, --multiline          let ^ and $ match at the start and end of every line");
+    eprintln!("  -s, --dot-all            let . match line breaks too");
+    eprintln!("      --encoding <name>    read and write the files as utf-8, utf-16, utf-16le, utf-16be or latin-1,");
+    eprintln!("                           rather than detecting their encoding");
+    eprintln!("  -l, --lines              replace line by line while reading, to handle inputs of any size");
+    eprintln!("      --dry-run            show the changes as a diff with the number of matches, without writing");
+    eprintln!("      --json               print every replacement and the totals as JSON, rather than a summary");
+    eprintln!("      --interactive        ask whether to replace every match, showing it within its lines");
+    eprintln!("      --in-place           replace in the files themselves, keeping their permissions and timestamps");
+    eprintln!("      --backup <suffix>    keep a copy of every changed file with the suffix, such as '.bak'");
+    eprintln!("      --no-journal         do not record the changed files, so the run cannot be undone");
+    eprintln!("  -r, --recursive          replace in every file under the paths, in place, honouring .gitignore");
+    eprintln!("                           and skipping hidden and binary files");
+    eprintln!("  -g, --glob <pattern>     only replace in the files matching the glob, such as '*.rs'");
+    eprintln!("  -e, --ext <extension>    only replace in the files with the extension, such as 'rs'");
 }
 
-fn parse_args() -> Argument {
-    let args: Vec<String> = env::args().skip(1).collect();
+fn replace_file(rules: &Rules, filename: &str, output: &str, write: Write, json: bool, encoding: Option<Encoding>)
+                -> Statistics {
+    let data = match stream::read(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{} Failed to read from file: '{}': {:?}", "Error".red().bold(), filename, e);
+            std::process::exit(1);
+        }
+    };
+    let Decoded { text: data, format } = match encoding::decode(&data, encoding.unwrap_or_else(|| encoding::detect(&data))) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{} Failed to read from file: '{}': {}", "Error".red().bold(), filename, e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut statistics = Statistics::new(rules.len());
+    if json {
+        statistics.changes = report::changes(filename, &data, rules, 1);
+    }
+    let replaced = match write {
+        Write::DryRun { colour } => {
+            let replaced = rules.replace(&data);
+            if !json {
+                print!("{}", dry_run(&data, &replaced, filename, output, colour));
+            }
+            statistics.add(&replaced.counts);
+            return statistics;
+        }
+        Write::Interactive { colour, .. } => match ask(rules, &data, filename, colour) {
+            Ok(choices) => Replaced { text: choices.text, counts: choices.counts },
+            Err(e) => {
+                eprintln!("{} {}", "Error".red().bold(), e);
+                std::process::exit(1);
+            }
+        },
+        Write::Files { .. } => rules.replace(&data),
+    };
 
-    if args.len() != 4 {
-        print_usage();
-        eprintln!("{} - wrong number of arguments. Expected 4 but got {}", "Error".red().bold(), args.len());
+    let result = encoding::encode(&replaced.text, &format)
+        .and_then(|data| stream::write(output, &data).map_err(|e| format!("{:?}", e)));
+    if let Err(e) = result {
+        eprintln!("{} Failed to write data to file: '{}': {}", "Error".red().bold(), output, e);
         std::process::exit(1);
     }
+    statistics.add(&replaced.counts);
+    statistics
+}
+
+fn replace_lines(rules: &Rules, filename: &str, output: &str, json: bool) -> Statistics {
+    let mut changes = Vec::new();
+    match stream::replace_lines_between(rules, filename, output, if json { Some(&mut changes) } else { None }) {
+        Ok(counts) => {
+            let mut statistics = Statistics::new(rules.len());
+            statistics.add(&counts);
+            statistics.changes = changes;
+            statistics
+        }
+        Err(e) => {
+            eprintln!("{} Failed to replace lines from '{}' to '{}': {:?}", "Error".red().bold(), filename, output, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Replaces in every file in parallel, writing back only the files that changed.  The output of a dry run is printed
+/// in the order of the files once they are all done, so that the diffs of different files never interleave.
+fn replace_files(rules: &Rules, files: &[PathBuf], write: Write, json: bool, encoding: Option<Encoding>) -> Statistics {
+    if let Write::Interactive { backup, journal, colour } = write {
+        return replace_interactively(rules, files, backup, journal, colour, encoding);
+    }
+
+    let results: Vec<Result<Outcome, String>> = files.par_iter()
+        .map(|file| replace_in_place(rules, file, write, json, encoding))
+        .collect();
+
+    let mut statistics = Statistics::new(rules.len());
+    let mut failures = 0;
+    for result in results {
+        match result {
+            Ok(outcome) => {
+                print!("{}", outcome.output);
+                statistics.add(&outcome.counts);
+                statistics.changes.extend(outcome.changes);
+            }
+            Err(e) => {
+                eprintln!("{} {}", "Error".red().bold(), e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        std::process::exit(1);
+    }
+    statistics
+}
+
+/// Binary files, files that cannot be decoded, files without any match and files whose matches are replaced with the
+/// same text are left untouched.  Returns what is to be printed for the file.
+fn replace_in_place(rules: &Rules, file: &Path, write: Write, json: bool, encoding: Option<Encoding>)
+                    -> Result<Outcome, String> {
+    let Decoded { text, format } = match read_text(rules, file, encoding)? {
+        Some(decoded) => decoded,
+        None => return Ok(Outcome::default()),
+    };
+
+    let name = file.display().to_string();
+    let changes = if json { report::changes(&name, &text, rules, 1) } else { Vec::new() };
+    let replaced = rules.replace(&text);
+    if replaced.text == text {
+        return Ok(Outcome::default());
+    }
+    let output = match write {
+        Write::DryRun { .. } if json => String::new(),
+        Write::DryRun { colour } => dry_run(&text, &replaced, &name, &name, colour),
+        Write::Files { backup, journal } | Write::Interactive { backup, journal, .. } => {
+            write_file(file, &replaced.text, &format, backup, journal)?;
+            String::new()
+        }
+    };
+    Ok(Outcome { output, counts: replaced.counts, changes })
+}
+
+/// Asks about the matches in the files one after the other, until the user quits
+fn replace_interactively(rules: &Rules, files: &[PathBuf], backup: Option<&str>, journal: Option<&Journal>,
+                         colour: bool, encoding: Option<Encoding>) -> Statistics {
+    let mut statistics = Statistics::new(rules.len());
+    for file in files {
+        let result = read_text(rules, file, encoding).and_then(|decoded| match decoded {
+            Some(Decoded { text, format }) => {
+                let choices = ask(rules, &text, &file.display().to_string(), colour)?;
+                if choices.text != text {
+                    write_file(file, &choices.text, &format, backup, journal)?;
+                }
+                statistics.add(&choices.counts);
+                Ok(choices.quit)
+            }
+            None => Ok(false),
+        });
+
+        match result {
+            Ok(false) => {}
+            Ok(true) => break,
+            Err(e) => {
+                eprintln!("{} {}", "Error".red().bold(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    statistics
+}
+
+fn ask(rules: &Rules, text: &str, name: &str, colour: bool) -> Result<interactive::Choices, String> {
+    interactive::choose(rules, text, name, &mut io::stdin().lock(), &mut io::stdout(), colour)
+        .map_err(|e| format!("Failed to ask about the matches in file: '{}': {:?}", name, e))
+}
+
+/// The text of the file in the given encoding, or else the detected one, unless the file is binary, cannot be decoded
+/// or has no match
+fn read_text(rules: &Rules, file: &Path, encoding: Option<Encoding>) -> Result<Option<Decoded>, String> {
+    let data = fs::read(file)
+        .map_err(|e| format!("Failed to read from file: '{}': {:?}", file.display(), e))?;
+    let encoding = encoding.unwrap_or_else(|| encoding::detect(&data));
+    if encoding::is_binary(&data, encoding) {
+        return Ok(None);
+    }
+    Ok(encoding::decode(&data, encoding).ok().filter(|decoded| rules.is_match(&decoded.text)))
+}
+
+/// Writes the text back in the format the file had, once the file as it was is recorded in the journal
+fn write_file(file: &Path, replaced: &str, format: &Format, backup: Option<&str>, journal: Option<&Journal>)
+              -> Result<(), String> {
+    let data = encoding::encode(replaced, format)
+        .map_err(|e| format!("Failed to write data to file: '{}': {}", file.display(), e))?;
+    if let Some(journal) = journal {
+        journal.record(file, &data)
+            .map_err(|e| format!("Failed to record file in the journal: '{}': {:?}", file.display(), e))?;
+    }
+    inplace::write_in_place(file, &data, backup).map_err(|e| {
+        // The file is as it was, so undoing the run must not restore it
+        let discarded = journal.map_or(Ok(()), |journal| journal.discard(file));
+        match discarded {
+            Ok(()) => format!("Failed to write data to file: '{}': {:?}", file.display(), e),
+            Err(d) => format!("Failed to write data to file: '{}': {:?}, nor drop it from the journal: {:?}",
+                              file.display(), e, d),
+        }
+    })
+}
+
+/// The diff of the replacement followed by the number of matches in the file
+fn dry_run(text: &str, replaced: &Replaced, old_name: &str, new_name: &str, colour: bool) -> String {
+    let matches: usize = replaced.counts.iter().sum();
+    format!("{}{}: {} {}\n", diff::unified_diff(text, &replaced.text, old_name, new_name, colour), old_name, matches,
+            if matches == 1 { "match" } else { "matches" })
+}
+
+fn parse_args(args: &[String]) -> Result<Argument, String> {
+    let mut recursive = false;
+    let mut in_place = false;
+    let mut dry_run = false;
+    let mut interactive = false;
+    let mut lines = false;
+    let mut json = false;
+    let mut encoding = None;
+    let mut pattern = pattern::Options::default();
+    let mut backup = None;
+    let mut journal = true;
+    let mut rules = None;
+    let mut globs = Vec::new();
+    let mut positional = Vec::new();
 
-    Argument {
-        target: args[0].clone(),
-        replacement: args[1].clone(),
-        filename: args[2].clone(),
-        output: args[3].clone(),
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Everything after `--` is positional, so that targets may start with a dash
+            "--" => positional.extend(args.by_ref().cloned()),
+            "-r" | "--recursive" => recursive = true,
+            "--in-place" => in_place = true,
+            "--dry-run" => dry_run = true,
+            "--interactive" => interactive = true,
+            "-l" | "--lines" => lines = true,
+            "--json" => json = true,
+            "--encoding" => encoding = Some(Encoding::parse(value(arg, args.next())?)?),
+            "-F" | "--fixed-strings" => pattern.fixed = true,
+            "-w" | "--word" => pattern.word = true,
+            "-i" | "--ignore-case" => pattern.ignore_case = true,
+            "-m" | "--multiline" => pattern.multiline = true,
+            "-s" | "--dot-all" => pattern.dot_all = true,
+            "--rules" => rules = Some(value(arg, args.next())?.to_owned()),
+            "--backup" => backup = Some(value(arg, args.next())?.to_owned()),
+            "--no-journal" => journal = false,
+            "-g" | "--glob" => globs.push(value(arg, args.next())?.to_owned()),
+            "-e" | "--ext" => globs.push(format!("*.{}", value(arg, args.next())?.trim_start_matches('.'))),
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("unknown option '{}'", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    if !recursive && !globs.is_empty() {
+        return Err("--glob and --ext can only be used with --recursive".to_owned());
+    }
+    if !recursive && !in_place && backup.is_some() {
+        return Err("--backup can only be used with --in-place or --recursive".to_owned());
+    }
+    if !recursive && !in_place && !journal {
+        return Err("--no-journal can only be used with --in-place or --recursive".to_owned());
     }
+    if dry_run && interactive {
+        return Err("--dry-run and --interactive cannot be used together".to_owned());
+    }
+    if lines && (recursive || in_place) {
+        return Err("--lines can only be used with an input and an output file".to_owned());
+    }
+    if lines && (dry_run || interactive) {
+        return Err("--lines cannot be used with --dry-run or --interactive".to_owned());
+    }
+    if lines && encoding.is_some_and(|encoding| encoding != Encoding::Utf8) {
+        return Err("--lines only reads UTF-8".to_owned());
+    }
+    if json && interactive {
+        return Err("--json cannot be used with --interactive".to_owned());
+    }
+    if backup.as_deref() == Some("") {
+        return Err("--backup needs a suffix, such as '.bak'".to_owned());
+    }
+
+    // The target and the replacement come first, unless they are in a rules file
+    let (target, skip) = match rules {
+        Some(path) => (Target::Rules { path }, 0),
+        None if positional.len() >= 2 => {
+            (Target::Single { target: positional[0].clone(), replacement: positional[1].clone() }, 2)
+        }
+        None => return Err(format!("wrong number of arguments. Expected at least 2 but got {}", positional.len())),
+    };
+    let mut positional = positional.split_off(skip);
+
+    let input = if recursive || in_place {
+        if positional.is_empty() {
+            return Err(format!("wrong number of arguments. Expected at least {} but got {}", skip + 1, skip));
+        }
+        if recursive {
+            Input::Recursive { paths: positional, globs }
+        } else {
+            Input::InPlace { files: positional }
+        }
+    } else {
+        if positional.len() != 2 {
+            return Err(format!("wrong number of arguments. Expected {} but got {}", skip + 2, skip + positional.len()));
+        }
+        if interactive && positional[0] == stream::STDIO {
+            return Err("--interactive reads the answers from stdin, so the input must be a file".to_owned());
+        }
+        if interactive && positional[1] == stream::STDIO {
+            return Err("--interactive prints the matches to stdout, so the output must be a file".to_owned());
+        }
+        if json && !dry_run && positional[1] == stream::STDIO {
+            return Err("--json prints the report to stdout, so the output must be a file".to_owned());
+        }
+        let output = positional.pop().unwrap();
+        Input::File { filename: positional.pop().unwrap(), output }
+    };
+
+    Ok(Argument {
+        target,
+        pattern,
+        input,
+        backup,
+        journal,
+        dry_run,
+        interactive,
+        lines,
+        json,
+        encoding,
+    })
 }
 
-fn replace(target: &str, replacement: &str, text: &str) -> Result<String, regex::Error> {
-    let regex = Regex::new(target)?;
-    Ok(regex.replace_all(text, replacement).to_string())
+fn value<'a>(flag: &str, value: Option<&'a String>) -> Result<&'a str, String> {
+    value.map(|value| value.as_str()).ok_or_else(|| format!("missing value for option {}", flag))
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Argument {
-    target: String,
-    replacement: String,
-    filename: String,
-    output: String,
+    target: Target,
+    pattern: pattern::Options,
+    input: Input,
+    /// The suffix of the copies kept of the files changed in place
+    backup: Option<String>,
+    /// Record the files changed in place in the journal, so that the run can be undone
+    journal: bool,
+    /// Show the changes instead of making them
+    dry_run: bool,
+    /// Ask before making every replacement
+    interactive: bool,
+    /// Stream the input file line by line, rather than reading it whole
+    lines: bool,
+    /// Report every replacement as JSON
+    json: bool,
+    /// The encoding of the files, detected for every file when not given
+    encoding: Option<Encoding>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Target {
+    Single {
+        target: String,
+        replacement: String,
+    },
+    /// The targets and their replacements are in the rules file
+    Rules {
+        path: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum Input {
+    File {
+        filename: String,
+        output: String,
+    },
+    /// Every file given, replaced in place
+    InPlace {
+        files: Vec<String>,
+    },
+    /// Every file under the paths, replaced in place
+    Recursive {
+        paths: Vec<String>,
+        globs: Vec<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_owned()).collect()
+    }
+
+    fn rules(target: &str, replacement: &str) -> Rules {
+        Rules::new(&[(target.to_owned(), replacement.to_owned())], &pattern::Options::default()).unwrap()
+    }
+
+    #[test]
+    fn parses_single_file() {
+        assert_eq!(parse_args(&args("Commands Useful README.md UPDATED.md")), Ok(Argument {
+            target: Target::Single { target: "Commands".to_owned(), replacement: "Useful".to_owned() },
+            pattern: pattern::Options::default(),
+            input: Input::File { filename: "README.md".to_owned(), output: "UPDATED.md".to_owned() },
+            backup: None,
+            journal: true,
+            dry_run: false,
+            interactive: false,
+            lines: false,
+            json: false,
+            encoding: None,
+        }));
+        assert_eq!(parse_args(&args("Commands Useful README.md")),
+                   Err("wrong number of arguments. Expected 4 but got 3".to_owned()));
+    }
+
+    #[test]
+    fn parses_pipelines() {
+        let parsed = parse_args(&args("--lines old new - -")).unwrap();
+        assert_eq!(parsed.input, Input::File { filename: "-".to_owned(), output: "-".to_owned() });
+        assert!(parsed.lines);
+
+        assert_eq!(parse_args(&args("-l -r old new src")),
+                   Err("--lines can only be used with an input and an output file".to_owned()));
+        assert_eq!(parse_args(&args("-l --dry-run old new - -")),
+                   Err("--lines cannot be used with --dry-run or --interactive".to_owned()));
+        assert_eq!(parse_args(&args("--interactive old new - out.txt")),
+                   Err("--interactive reads the answers from stdin, so the input must be a file".to_owned()));
+        assert_eq!(parse_args(&args("--interactive old new in.txt -")),
+                   Err("--interactive prints the matches to stdout, so the output must be a file".to_owned()));
+    }
+
+    #[test]
+    fn parses_pattern_options() {
+        let parsed = parse_args(&args("-F -w -i -m -s old new a.txt b.txt")).unwrap();
+        assert_eq!(parsed.pattern, pattern::Options {
+            fixed: true,
+            word: true,
+            ignore_case: true,
+            multiline: true,
+            dot_all: true,
+        });
+        assert!(parse_args(&args("--ignore-case old new a.txt b.txt")).unwrap().pattern.ignore_case);
+    }
+
+    #[test]
+    fn parses_recursive() {
+        let parsed = parse_args(&args("-r --glob *.rs --ext .toml old new src tests")).unwrap();
+        assert_eq!(parsed.input, Input::Recursive {
+            paths: vec!["src".to_owned(), "tests".to_owned()],
+            globs: vec!["*.rs".to_owned(), "*.toml".to_owned()],
+        });
+
+        assert_eq!(parse_args(&args("--glob *.rs old new a b")),
+                   Err("--glob and --ext can only be used with --recursive".to_owned()));
+        assert_eq!(parse_args(&args("-r old new")),
+                   Err("wrong number of arguments. Expected at least 3 but got 2".to_owned()));
+        assert_eq!(parse_args(&args("-r old")),
+                   Err("wrong number of arguments. Expected at least 2 but got 1".to_owned()));
+        assert_eq!(parse_args(&args("-r --colour old new src")), Err("unknown option '--colour'".to_owned()));
+    }
+
+    #[test]
+    fn parses_rules() {
+        let parsed = parse_args(&args("--rules renames.toml -r src tests")).unwrap();
+        assert_eq!(parsed.target, Target::Rules { path: "renames.toml".to_owned() });
+        assert_eq!(parsed.input, Input::Recursive {
+            paths: vec!["src".to_owned(), "tests".to_owned()],
+            globs: vec![],
+        });
+
+        let parsed = parse_args(&args("--rules renames.yaml - out.txt")).unwrap();
+        assert_eq!(parsed.input, Input::File { filename: "-".to_owned(), output: "out.txt".to_owned() });
+        assert_eq!(parse_args(&args("--rules renames.yaml old new a.txt b.txt")),
+                   Err("wrong number of arguments. Expected 2 but got 4".to_owned()));
+        assert_eq!(parse_args(&args("--rules renames.yaml --in-place")),
+                   Err("wrong number of arguments. Expected at least 1 but got 0".to_owned()));
+    }
+
+    #[test]
+    fn parses_in_place() {
+        let parsed = parse_args(&args("--in-place --backup .bak old new a.txt b.txt")).unwrap();
+        assert_eq!(parsed.input, Input::InPlace { files: vec!["a.txt".to_owned(), "b.txt".to_owned()] });
+        assert_eq!(parsed.backup, Some(".bak".to_owned()));
+
+        assert_eq!(parse_args(&args("--backup .bak old new a.txt b.txt")),
+                   Err("--backup can only be used with --in-place or --recursive".to_owned()));
+        assert_eq!(parse_args(&args("--in-place old new")),
+                   Err("wrong number of arguments. Expected at least 3 but got 2".to_owned()));
+    }
+
+    #[test]
+    fn replaces_in_place_skipping_binary_and_other_files() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        fs::write(directory.join("text.txt"), "old and old").unwrap();
+        fs::write(directory.join("binary.bin"), b"old\x00old").unwrap();
+        fs::write(directory.join("other.dat"), b"old \xFF\xFE\x80 old").unwrap();
+
+        let files = files::find_files(&[directory.display().to_string()], &[]).unwrap();
+        let write = Write::Files { backup: None, journal: None };
+        let statistics = replace_files(&rules("old", "new"), &files, write, false, None);
+        assert_eq!((statistics.scanned, statistics.changed, statistics.total()), (3, 1, 2));
+
+        assert_eq!(fs::read_to_string(directory.join("text.txt")).unwrap(), "new and new");
+        assert_eq!(fs::read(directory.join("binary.bin")).unwrap(), b"old\x00old");
+        // Neither UTF-8 nor UTF-16, and without any zero byte
+        assert_eq!(fs::read(directory.join("other.dat")).unwrap(), b"old \xFF\xFE\x80 old");
+    }
+
+    #[test]
+    fn dry_runs_leave_files_untouched() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let file = directory.join("text.txt");
+        fs::write(&file, "old and old\nkept\n").unwrap();
+
+        let outcome = replace_in_place(&rules("old", "new"), &file, Write::DryRun { colour: false }, false, None).unwrap();
+        let name = file.display();
+        assert_eq!(outcome.counts, vec![2]);
+        assert_eq!(outcome.output, format!("--- {0}\n+++ {0}\n@@ -1,2 +1,2 @@\n-old and old\n+new and new\n kept\n{0}: 2 matches\n",
+                                   name));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old and old\nkept\n");
+
+        assert!(parse_args(&args("--dry-run -r old new src")).unwrap().dry_run);
+        assert_eq!(parse_args(&args("--dry-run --interactive -r old new src")),
+                   Err("--dry-run and --interactive cannot be used together".to_owned()));
+
+        let outcome = replace_in_place(&rules("old", "new"), &file, Write::DryRun { colour: false }, true, None).unwrap();
+        assert_eq!(outcome.output, "");
+        assert_eq!(outcome.changes.iter().map(|change| change.column).collect::<Vec<_>>(), vec![1, 9]);
+    }
+
+    #[test]
+    fn keeps_the_encoding_bom_and_line_endings() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let utf16 = directory.join("utf16.txt");
+        fs::write(&utf16, b"\xFF\xFEo\x00l\x00d\x00\r\x00\n\x00").unwrap();
+        let latin1 = directory.join("latin1.txt");
+        fs::write(&latin1, b"old caf\xE9\r\nold\r\n").unwrap();
+
+        let write = Write::Files { backup: None, journal: None };
+        replace_files(&rules("old", "new"), std::slice::from_ref(&utf16), write, false, None);
+        assert_eq!(fs::read(&utf16).unwrap(), b"\xFF\xFEn\x00e\x00w\x00\r\x00\n\x00");
+        // Latin-1 is only used when asked for, as anything can be read as Latin-1
+        replace_files(&rules("old", "new"), std::slice::from_ref(&latin1), write, false, None);
+        assert_eq!(fs::read(&latin1).unwrap(), b"old caf\xE9\r\nold\r\n");
+        replace_files(&rules("old", "new"), std::slice::from_ref(&latin1), write, false, Some(Encoding::Latin1));
+        assert_eq!(fs::read(&latin1).unwrap(), b"new caf\xE9\r\nnew\r\n");
+
+        assert_eq!(parse_args(&args("--encoding latin1 -r old new src")).unwrap().encoding, Some(Encoding::Latin1));
+        assert_eq!(parse_args(&args("--encoding utf-16 --lines old new - -")),
+                   Err("--lines only reads UTF-8".to_owned()));
+    }
+
+    #[test]
+    fn parses_json() {
+        assert!(parse_args(&args("--json -r old new src")).unwrap().json);
+        assert!(parse_args(&args("--json --dry-run old new - -")).unwrap().json);
+        assert_eq!(parse_args(&args("--json old new - -")),
+                   Err("--json prints the report to stdout, so the output must be a file".to_owned()));
+        assert_eq!(parse_args(&args("--json --interactive -r old new src")),
+                   Err("--json cannot be used with --interactive".to_owned()));
+    }
+
+    #[test]
+    fn leaves_files_replaced_with_the_same_text_alone() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let file = directory.join("k.txt");
+        fs::write(&file, "keep").unwrap();
+
+        let journal = Journal::new(&directory.join("journal"));
+        let write = Write::Files { backup: Some(".bak"), journal: Some(&journal) };
+        let statistics = replace_files(&rules("keep", "keep"), std::slice::from_ref(&file), write, false, None);
+
+        assert_eq!(statistics.total(), 0);
+        assert!(!directory.join("k.txt.bak").exists());
+        assert!(!journal.is_used());
+    }
+
+    #[test]
+    fn undoes_runs_recorded_in_the_journal() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        let file = directory.join("text.txt");
+        fs::write(&file, "old and old").unwrap();
+
+        let journal = Journal::new(&directory.join("journal"));
+        let write = Write::Files { backup: None, journal: Some(&journal) };
+        replace_files(&rules("old", "new"), std::slice::from_ref(&file), write, false, None);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new and new");
+
+        let undone = journal::undo(&directory.join("journal"), Some(journal.id())).unwrap();
+        assert_eq!(undone.restored, vec![fs::canonicalize(&file).unwrap()]);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old and old");
+    }
+
+    #[test]
+    fn parses_undo() {
+        assert!(is_undo(&args("undo")));
+        assert!(is_undo(&args("undo 1760000000000-42")));
+        // Replacing the word undo
+        assert!(!is_undo(&args("undo redo a.txt b.txt")));
+        assert!(!is_undo(&args("--in-place undo redo")));
+
+        assert!(!parse_args(&args("--no-journal -r old new src")).unwrap().journal);
+        assert_eq!(parse_args(&args("--no-journal old new a.txt b.txt")),
+                   Err("--no-journal can only be used with --in-place or --recursive".to_owned()));
+    }
 }
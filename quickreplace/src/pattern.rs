@@ -0,0 +1,132 @@
+use regex::Regex;
+
+/// How the target is matched
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Options {
+    /// The target is plain text rather than a regular expression, and so is the replacement
+    pub(crate) fixed: bool,
+    /// Only match whole words
+    pub(crate) word: bool,
+    pub(crate) ignore_case: bool,
+    /// `^` and `$` match at the start and end of every line, rather than of the whole text only
+    pub(crate) multiline: bool,
+    /// `.` matches line breaks too
+    pub(crate) dot_all: bool,
+}
+
+/// Compiles the target with the options written as inline flags, such as `(?i)`, so that the pattern of the regex can
+/// be compiled again into a `RegexSet` behaving the same
+pub(crate) fn compile(target: &str, options: &Options) -> Result<Regex, regex::Error> {
+    let mut pattern = if options.fixed { regex::escape(target) } else { target.to_owned() };
+    if options.word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+
+    let flags: String = [(options.ignore_case, 'i'), (options.multiline, 'm'), (options.dot_all, 's')].iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, flag)| flag)
+        .collect();
+    if !flags.is_empty() {
+        pattern = format!("(?{}){}", flags, pattern);
+    }
+    Regex::new(&pattern)
+}
+
+/// The replacement as expected by `Regex::replace_all`.  Fixed replacements have their `$` escaped, while every group
+/// referred to by the others, such as `$1`, `$name` or `${name}`, must be in the regex, as `Regex::replace_all` would
+/// otherwise quietly replace it with nothing.
+pub(crate) fn replacement(regex: &Regex, replacement: &str, options: &Options) -> Result<String, String> {
+    if options.fixed {
+        return Ok(replacement.replace('$', "$$"));
+    }
+
+    for group in references(replacement) {
+        let known = match group.parse::<usize>() {
+            Ok(index) => index < regex.captures_len(),
+            Err(_) => regex.capture_names().flatten().any(|name| name == group),
+        };
+        if !known {
+            return Err(format!("unknown capture group '{}' in the replacement '{}'", group, replacement));
+        }
+    }
+    Ok(replacement.to_owned())
+}
+
+/// The groups referred to in the replacement, following the rules of `Captures::expand`: `$$` is a dollar sign, and
+/// `$name` takes the longest run of letters, digits and underscores, so `${1}a` is needed where `$1a` is meant
+fn references(replacement: &str) -> Vec<&str> {
+    let mut groups = Vec::new();
+    let mut rest = replacement;
+
+    while let Some(index) = rest.find('$') {
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            if let Some(end) = braced.find('}') {
+                groups.push(&braced[..end]);
+                rest = &braced[end + 1..];
+            }
+        } else {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            if end > 0 {
+                groups.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(target: &str, replacement: &str, text: &str, options: Options) -> String {
+        let regex = compile(target, &options).unwrap();
+        let replacement = super::replacement(&regex, replacement, &options).unwrap();
+        regex.replace_all(text, replacement.as_str()).to_string()
+    }
+
+    #[test]
+    fn matches_fixed_strings() {
+        let options = Options { fixed: true, ..Options::default() };
+        assert_eq!(replace("a.b(", "$1", "a.b( axb(", options), "$1 axb(");
+    }
+
+    #[test]
+    fn matches_words_ignoring_case() {
+        let options = Options { word: true, ignore_case: true, ..Options::default() };
+        assert_eq!(replace("cat", "dog", "Cat cats CAT", options), "dog cats dog");
+    }
+
+    #[test]
+    fn writes_options_as_inline_flags() {
+        let options = Options { ignore_case: true, dot_all: true, ..Options::default() };
+        assert_eq!(compile("a.b", &options).unwrap().as_str(), "(?is)a.b");
+        assert_eq!(compile("a.b", &Options::default()).unwrap().as_str(), "a.b");
+    }
+
+    #[test]
+    fn matches_across_lines() {
+        assert_eq!(replace("^x", "y", "x\nx", Options { multiline: true, ..Options::default() }), "y\ny");
+        assert_eq!(replace("a.b", "-", "a\nb", Options { dot_all: true, ..Options::default() }), "-");
+        assert_eq!(replace("a.b", "-", "a\nb", Options::default()), "a\nb");
+    }
+
+    #[test]
+    fn replaces_named_groups() {
+        assert_eq!(replace(r"(?P<key>\w+)=(?P<value>\w+)", "${value}=$key$$", "a=1", Options::default()), "1=a$");
+    }
+
+    #[test]
+    fn rejects_unknown_groups() {
+        let regex = compile(r"(?P<key>\w+)=(\w+)", &Options::default()).unwrap();
+        assert!(replacement(&regex, "$2 ${key} $$name", &Options::default()).is_ok());
+        assert_eq!(replacement(&regex, "$3", &Options::default()),
+                   Err("unknown capture group '3' in the replacement '$3'".to_owned()));
+        assert_eq!(replacement(&regex, "$keys", &Options::default()),
+                   Err("unknown capture group 'keys' in the replacement '$keys'".to_owned()));
+        assert_eq!(references("$1a ${1}a $ $"), vec!["1a", "1"]);
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+
+use crate::rules::Rules;
+
+/// What a run did, added up over all the files it looked at
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Statistics {
+    pub(crate) scanned: usize,
+    pub(crate) changed: usize,
+    /// The number of replacements every rule made
+    pub(crate) replacements: Vec<usize>,
+    /// The number of files every rule made replacements in
+    pub(crate) files: Vec<usize>,
+    /// Every replacement made, only collected for the JSON report
+    pub(crate) changes: Vec<Change>,
+}
+
+/// One replacement, where the line and the column, counted in characters, both start from 1
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Change {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) original: String,
+    pub(crate) replacement: String,
+}
+
+/// The layout of the JSON report
+#[derive(Serialize)]
+struct Report<'a> {
+    files_scanned: usize,
+    files_changed: usize,
+    replacements: usize,
+    changes: &'a [Change],
+}
+
+impl Statistics {
+    pub(crate) fn new(rules: usize) -> Statistics {
+        Statistics { scanned: 0, changed: 0, replacements: vec![0; rules], files: vec![0; rules], changes: Vec::new() }
+    }
+
+    /// Adds one scanned file, with the number of replacements every rule made in it, which is empty when the file was
+    /// skipped
+    pub(crate) fn add(&mut self, counts: &[usize]) {
+        self.scanned += 1;
+        if counts.iter().any(|&count| count > 0) {
+            self.changed += 1;
+        }
+        for (index, &count) in counts.iter().enumerate() {
+            self.replacements[index] += count;
+            if count > 0 {
+                self.files[index] += 1;
+            }
+        }
+    }
+
+    pub(crate) fn total(&self) -> usize {
+        self.replacements.iter().sum()
+    }
+
+    pub(crate) fn summary(&self, dry_run: bool) -> String {
+        let total = self.total();
+        format!("{} {} {} in {} of {} {}\n", if dry_run { "Would replace" } else { "Replaced" }, total,
+                if total == 1 { "match" } else { "matches" }, self.changed, self.scanned,
+                if self.scanned == 1 { "file" } else { "files" })
+    }
+
+    pub(crate) fn json(&self) -> String {
+        let report = Report {
+            files_scanned: self.scanned,
+            files_changed: self.changed,
+            replacements: self.total(),
+            changes: &self.changes,
+        };
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+}
+
+/// The replacements the rules make in the text, whose first line is `first_line` of the file
+pub(crate) fn changes(file: &str, text: &str, rules: &Rules, first_line: usize) -> Vec<Change> {
+    let (mut line, mut line_start, mut position) = (first_line, 0, 0);
+
+    rules.matches(text)
+        .map(|found| {
+            for (index, _) in text[position..found.start].match_indices('\n') {
+                line += 1;
+                line_start = position + index + 1;
+            }
+            position = found.start;
+
+            Change {
+                file: file.to_owned(),
+                line,
+                column: text[line_start..found.start].chars().count() + 1,
+                original: found.original.to_owned(),
+                replacement: found.replacement,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern;
+
+    use super::*;
+
+    fn rules(target: &str, replacement: &str) -> Rules {
+        Rules::new(&[(target.to_owned(), replacement.to_owned())], &pattern::Options::default()).unwrap()
+    }
+
+    #[test]
+    fn locates_the_changes() {
+        let changes = changes("a.txt", "old\n  éold old\n\nold", &rules("old", "new"), 1);
+        let locations: Vec<(usize, usize)> = changes.iter().map(|change| (change.line, change.column)).collect();
+        assert_eq!(locations, vec![(1, 1), (2, 4), (2, 8), (4, 1)]);
+        assert_eq!(changes[0], Change {
+            file: "a.txt".to_owned(),
+            line: 1,
+            column: 1,
+            original: "old".to_owned(),
+            replacement: "new".to_owned(),
+        });
+    }
+
+    #[test]
+    fn summarises_the_files() {
+        let mut statistics = Statistics::new(1);
+        statistics.add(&[2]);
+        statistics.add(&[0]);
+        statistics.add(&[]);
+        assert_eq!(statistics.summary(false), "Replaced 2 matches in 1 of 3 files\n");
+        assert_eq!(Statistics::new(1).summary(true), "Would replace 0 matches in 0 of 0 files\n");
+    }
+
+    #[test]
+    fn reports_json() {
+        let mut statistics = Statistics::new(1);
+        statistics.add(&[1]);
+        statistics.changes = changes("a.txt", "x old", &rules("old", "new"), 3);
+
+        let json: serde_json::Value = serde_json::from_str(&statistics.json()).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "files_scanned": 1,
+            "files_changed": 1,
+            "replacements": 1,
+            "changes": [{ "file": "a.txt", "line": 3, "column": 3, "original": "old", "replacement": "new" }],
+        }));
+    }
+}
@@ -0,0 +1,294 @@
+//! Replacing several targets in a single pass.  The `RegexSet` of all the targets first tells which of them occur in
+//! the text at all, and only those are then searched, always replacing the leftmost match next, so that every part of
+//! the text is replaced at most once and the replacement of one rule is never matched by another.
+
+use std::fs;
+use std::path::Path;
+
+use regex::{Captures, Regex, RegexSet};
+use serde::Deserialize;
+
+use crate::pattern;
+use crate::report::Statistics;
+
+/// The targets and their replacements, in the order the rules are tried when several match at the same place
+#[derive(Debug)]
+pub(crate) struct Rules {
+    rules: Vec<Rule>,
+    set: RegexSet,
+}
+
+#[derive(Debug)]
+struct Rule {
+    target: String,
+    replacement: String,
+    regex: Regex,
+    /// The replacement as expected by `Captures::expand`
+    expansion: String,
+}
+
+/// The layout of the rules files, the same for TOML and YAML
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    target: String,
+    replacement: String,
+}
+
+/// A match of one of the rules, and the text replacing it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Match<'t> {
+    /// The index of the rule that matched
+    pub(crate) rule: usize,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) original: &'t str,
+    pub(crate) replacement: String,
+}
+
+/// The text with every match replaced, and how many matches every rule replaced
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Replaced {
+    pub(crate) text: String,
+    pub(crate) counts: Vec<usize>,
+}
+
+impl Rules {
+    /// The rules replacing the targets in the given order
+    pub(crate) fn new(rules: &[(String, String)], options: &pattern::Options) -> Result<Rules, String> {
+        let rules = rules.iter()
+            .map(|(target, replacement)| {
+                let regex = pattern::compile(target, options)
+                    .map_err(|e| format!("invalid target '{}': {}", target, e))?;
+                let expansion = pattern::replacement(&regex, replacement, options)?;
+                Ok(Rule { target: target.clone(), replacement: replacement.clone(), regex, expansion })
+            })
+            .collect::<Result<Vec<Rule>, String>>()?;
+
+        let set = RegexSet::new(rules.iter().map(|rule| rule.regex.as_str())).map_err(|e| e.to_string())?;
+        Ok(Rules { rules, set })
+    }
+
+    /// Reads the rules from a TOML or YAML file, told apart by its extension, holding a list of `rules` each with a
+    /// `target` and a `replacement`
+    pub(crate) fn load(path: &Path, options: &pattern::Options) -> Result<Rules, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read from file: '{}': {:?}", path.display(), e))?;
+        let file: RulesFile = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&data).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&data).map_err(|e| e.to_string()),
+            _ => Err("the rules file must end with .toml, .yaml or .yml".to_owned()),
+        }.map_err(|e| format!("Failed to read the rules from file: '{}': {}", path.display(), e))?;
+
+        if file.rules.is_empty() {
+            return Err(format!("No rules in file: '{}'", path.display()));
+        }
+        let rules: Vec<(String, String)> = file.rules.into_iter()
+            .map(|rule| (rule.target, rule.replacement))
+            .collect();
+        Rules::new(&rules, options).map_err(|e| format!("Failed to read the rules from file: '{}': {}", path.display(), e))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        self.set.is_match(text)
+    }
+
+    /// The matches in the text from left to right, never overlapping.  When several rules match at the same place, the
+    /// first of them wins.
+    pub(crate) fn matches<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        let next = self.set.matches(text).into_iter()
+            .filter_map(|rule| self.rules[rule].regex.captures(text).map(|captures| (rule, captures)))
+            .collect();
+        Matches { rules: self, text, next, position: 0, last_end: None }
+    }
+
+    pub(crate) fn replace(&self, text: &str) -> Replaced {
+        // A single rule needs no merging of the matches of several
+        if let [rule] = self.rules.as_slice() {
+            let mut count = 0;
+            let text = rule.regex.replace_all(text, |captures: &Captures| {
+                count += 1;
+                let mut replacement = String::new();
+                captures.expand(&rule.expansion, &mut replacement);
+                replacement
+            });
+            return Replaced { text: text.into_owned(), counts: vec![count] };
+        }
+
+        let mut replaced = String::with_capacity(text.len());
+        let mut counts = vec![0; self.rules.len()];
+        let mut last = 0;
+
+        for found in self.matches(text) {
+            replaced.push_str(&text[last..found.start]);
+            replaced.push_str(&found.replacement);
+            counts[found.rule] += 1;
+            last = found.end;
+        }
+        replaced.push_str(&text[last..]);
+
+        Replaced { text: replaced, counts }
+    }
+
+    /// One line per rule, with the number of replacements it made and in how many files
+    pub(crate) fn report(&self, statistics: &Statistics) -> String {
+        self.rules.iter().enumerate()
+            .map(|(index, rule)| {
+                let (replacements, files) = (statistics.replacements[index], statistics.files[index]);
+                format!("Rule {}: '{}' -> '{}': {} {} in {} {}\n", index + 1, rule.target, rule.replacement,
+                        replacements, if replacements == 1 { "replacement" } else { "replacements" },
+                        files, if files == 1 { "file" } else { "files" })
+            })
+            .collect()
+    }
+}
+
+/// The iterator returned by `Rules::matches`, holding the next match of every rule that still matches
+pub(crate) struct Matches<'r, 't> {
+    rules: &'r Rules,
+    text: &'t str,
+    next: Vec<(usize, Captures<'t>)>,
+    /// Where the next match may start at the earliest
+    position: usize,
+    /// Where the previous match ended, where no empty match is found
+    last_end: Option<usize>,
+}
+
+impl<'t> Iterator for Matches<'_, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        // The matches overlapping the previous one, or empty where it ended, are searched again after it, dropping the
+        // rules matching no more
+        let (text, position, last_end) = (self.text, self.position, self.last_end);
+        let rules = &self.rules.rules;
+        self.next.retain_mut(|(rule, captures)| {
+            let found = captures.get(0).unwrap();
+            if found.start() >= position && !(found.is_empty() && Some(found.start()) == last_end) {
+                return true;
+            }
+            match search(&rules[*rule].regex, text, position, last_end) {
+                Some(found) => {
+                    *captures = found;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        // Ties go to the first rule, as the rules are kept in their order
+        let (rule, captures) = self.next.iter().min_by_key(|(_, captures)| captures.get(0).unwrap().start())?;
+        let found = captures.get(0).unwrap();
+        let mut replacement = String::new();
+        captures.expand(&rules[*rule].expansion, &mut replacement);
+
+        self.position = found.end();
+        self.last_end = Some(found.end());
+
+        Some(Match {
+            rule: *rule,
+            start: found.start(),
+            end: found.end(),
+            original: found.as_str(),
+            replacement,
+        })
+    }
+}
+
+/// The next match from the position, skipping an empty match where the previous match ended, as `Regex::replace_all`
+/// does
+fn search<'t>(regex: &Regex, text: &'t str, position: usize, last_end: Option<usize>) -> Option<Captures<'t>> {
+    let captures = regex.captures_at(text, position)?;
+    let found = captures.get(0).unwrap();
+    if found.is_empty() && Some(found.start()) == last_end {
+        let next = position + text[position..].chars().next()?.len_utf8();
+        return regex.captures_at(text, next);
+    }
+    Some(captures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[(&str, &str)]) -> Rules {
+        let rules: Vec<(String, String)> = rules.iter()
+            .map(|(target, replacement)| (target.to_string(), replacement.to_string()))
+            .collect();
+        Rules::new(&rules, &pattern::Options::default()).unwrap()
+    }
+
+    #[test]
+    fn replaces_in_a_single_pass() {
+        // Swapping needs a single pass, as the second rule would otherwise undo the first
+        let rules = rules(&[("cat", "dog"), ("dog", "cat"), ("bird", "fish")]);
+        assert_eq!(rules.replace("cat dog cat"), Replaced { text: "dog cat dog".to_owned(), counts: vec![2, 1, 0] });
+    }
+
+    #[test]
+    fn prefers_the_leftmost_and_then_the_first_rule() {
+        let rules = rules(&[("b", "1"), ("ab", "2"), ("abc", "3")]);
+        assert_eq!(rules.replace("abc b"), Replaced { text: "2c 1".to_owned(), counts: vec![1, 1, 0] });
+    }
+
+    #[test]
+    fn expands_capture_groups() {
+        let rules = rules(&[(r"(\w+)@(\w+)", "$2 at $1"), ("x*", "-")]);
+        let matches: Vec<Match> = rules.matches("a@b c").collect();
+        assert_eq!(matches[0].original, "a@b");
+        assert_eq!(matches[0].replacement, "b at a");
+        // The empty matches of the second rule fill the gaps without getting stuck
+        assert_eq!(rules.replace("a@b c").text, "b at a -c-");
+    }
+
+    #[test]
+    fn skips_empty_matches_where_the_previous_match_ended() {
+        for (target, replacement, text, expected) in [("a*", "-", "baaac", "-b-c-"), ("a|", "-", "banana", "-b-n-n-"),
+                                                      ("o*", "0", "foo bar", "0f0 0b0a0r0")] {
+            assert_eq!(rules(&[(target, replacement)]).replace(text).text, expected);
+            // A second rule takes the replacement through the matches, as used when asking about and reporting them
+            let two = rules(&[(target, replacement), ("xyz", "")]);
+            assert_eq!(two.replace(text).text, expected);
+        }
+    }
+
+    #[test]
+    fn loads_toml_and_yaml_files() {
+        let temporary = tempfile::tempdir().unwrap();
+        let directory = temporary.path();
+        fs::write(directory.join("rules.toml"),
+                  "[[rules]]\ntarget = 'old_name'\nreplacement = 'new_name'\n\n\
+                   [[rules]]\ntarget = 'OldType'\nreplacement = 'NewType'\n").unwrap();
+        fs::write(directory.join("rules.yaml"),
+                  "rules:\n  - target: old_name\n    replacement: new_name\n").unwrap();
+        fs::write(directory.join("unknown.yaml"), "rules:\n  - target: a\n    replacment: b\n").unwrap();
+        fs::write(directory.join("rules.json"), "{}").unwrap();
+
+        let options = pattern::Options::default();
+        let toml = Rules::load(&directory.join("rules.toml"), &options).unwrap();
+        assert_eq!(toml.replace("old_name: OldType").text, "new_name: NewType");
+        assert_eq!(Rules::load(&directory.join("rules.yaml"), &options).unwrap().len(), 1);
+        assert!(Rules::load(&directory.join("unknown.yaml"), &options).is_err());
+        assert!(Rules::load(&directory.join("rules.json"), &options).unwrap_err().contains(".toml, .yaml or .yml"));
+    }
+
+    #[test]
+    fn reports_statistics() {
+        let rules = rules(&[("a", "b"), ("c", "d")]);
+        let mut statistics = Statistics::new(2);
+        statistics.add(&rules.replace("a").counts);
+        statistics.add(&rules.replace("ac").counts);
+        assert_eq!(rules.report(&statistics),
+                   "Rule 1: 'a' -> 'b': 2 replacements in 2 files\nRule 2: 'c' -> 'd': 1 replacement in 1 file\n");
+    }
+}
@@ -0,0 +1,116 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+use crate::report::{self, Change};
+use crate::rules::Rules;
+
+/// The file name standing for stdin when reading, and stdout when writing
+pub(crate) const STDIO: &str = "-";
+
+/// Reads the whole file, or stdin
+pub(crate) fn read(filename: &str) -> Result<Vec<u8>, Error> {
+    if filename == STDIO {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(filename)
+    }
+}
+
+/// Writes the whole file, or stdout
+pub(crate) fn write(filename: &str, data: &[u8]) -> Result<(), Error> {
+    if filename == STDIO {
+        ignore_broken_pipe(io::stdout().lock().write_all(data))
+    } else {
+        fs::write(filename, data)
+    }
+}
+
+/// Replaces line by line from the input file to the output file, either of which may be stdin or stdout, so that only
+/// one line at a time is held in memory however large the input is.  Returns the number of replacements of every rule,
+/// and collects the replacements into `changes` when given.
+pub(crate) fn replace_lines_between(rules: &Rules, filename: &str, output: &str, changes: Option<&mut Vec<Change>>)
+                                    -> Result<Vec<usize>, Error> {
+    let input: Box<dyn BufRead> = if filename == STDIO {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(filename)?))
+    };
+    let output: Box<dyn Write> = if output == STDIO {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    let mut counts = vec![0; rules.len()];
+    let changes = changes.map(|changes| (filename, changes));
+    ignore_broken_pipe(replace_lines(rules, input, output, &mut counts, changes))?;
+    Ok(counts)
+}
+
+/// The line breaks are left out of the text being replaced, so that `$` matches at the end of every line, and are then
+/// written back as they were
+pub(crate) fn replace_lines(rules: &Rules, mut input: impl BufRead, mut output: impl Write, counts: &mut [usize],
+                            mut changes: Option<(&str, &mut Vec<Change>)>) -> Result<(), Error> {
+    let mut line = String::new();
+    let mut number = 1;
+    while input.read_line(&mut line)? > 0 {
+        let text = line.trim_end_matches('\n').trim_end_matches('\r');
+        if let Some((filename, changes)) = changes.as_mut() {
+            changes.extend(report::changes(filename, text, rules, number));
+        }
+        let replaced = rules.replace(text);
+        for (total, count) in counts.iter_mut().zip(replaced.counts) {
+            *total += count;
+        }
+        output.write_all(replaced.text.as_bytes())?;
+        output.write_all(&line.as_bytes()[text.len()..])?;
+        line.clear();
+        number += 1;
+    }
+    output.flush()
+}
+
+/// Output piped into a command that stops reading early, such as `head`, is not an error
+fn ignore_broken_pipe(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern;
+
+    use super::*;
+
+    fn rules(target: &str) -> Rules {
+        Rules::new(&[(target.to_owned(), "new".to_owned())], &pattern::Options::default()).unwrap()
+    }
+
+    #[test]
+    fn replaces_line_by_line_keeping_line_breaks() {
+        let mut output = Vec::new();
+        let mut counts = vec![0];
+        let mut changes = Vec::new();
+        replace_lines(&rules("old$"), "old\r\nold old\nkept\nold".as_bytes(), &mut output, &mut counts,
+                      Some(("-", &mut changes))).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "new\r\nold new\nkept\nnew");
+        assert_eq!(counts, vec![3]);
+        let locations: Vec<(usize, usize)> = changes.iter().map(|change| (change.line, change.column)).collect();
+        assert_eq!(locations, vec![(1, 1), (2, 5), (4, 1)]);
+    }
+
+    #[test]
+    fn fails_for_invalid_utf8() {
+        let error = replace_lines(&rules("old"), &b"old\n\xff\n"[..], Vec::new(), &mut [0], None).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ignores_broken_pipes() {
+        assert!(ignore_broken_pipe(Err(Error::from(ErrorKind::BrokenPipe))).is_ok());
+        assert!(ignore_broken_pipe(Err(Error::from(ErrorKind::NotFound))).is_err());
+    }
+}